@@ -0,0 +1,27 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+#version 330 core
+
+in vec2 tex_coord;
+out vec4 color;
+
+// tone mapped orbit density from buddhabrot.rs, rows top to bottom
+uniform sampler2D density;
+
+void main() {
+    color = vec4(texture(density, vec2(tex_coord.x, -tex_coord.y) * 0.5 + 0.5).rgb, 1.0);
+}
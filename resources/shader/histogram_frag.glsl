@@ -0,0 +1,64 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+#version 330 core
+
+in vec2 tex_coord;
+out vec4 color;
+
+// raw iteration counts of the first pass, -1 where the point never escaped
+uniform sampler2D iterations;
+// cumulative distribution of the counts between min_count and max_count, from histogram.rs
+uniform sampler2D cdf;
+uniform float min_count;
+uniform float max_count;
+
+// repeat modes as in palette.rs, the whole distribution is one pass over the gradient
+#define PALETTE_REPEAT 0
+#define PALETTE_MIRROR 1
+#define PALETTE_CLAMP 2
+uniform bool use_palette;
+uniform sampler2D palette;
+uniform float palette_offset;
+uniform int palette_repeat;
+
+// linear lookup between the first and the last texel centers
+vec4 lookup(sampler2D table, float t) {
+    float size = float(textureSize(table, 0).x);
+    return texture(table, vec2((t * (size - 1.0) + 0.5) / size, 0.5));
+}
+
+void main() {
+    float n = texelFetch(iterations, ivec2(gl_FragCoord.xy), 0).r;
+    float t = 1.0;
+    if (n >= 0.0) {
+        t = lookup(cdf, clamp((n - min_count) / max(max_count - min_count, 1e-6), 0.0, 1.0)).r;
+    }
+    if (use_palette) {
+        t += palette_offset;
+        if (palette_repeat == PALETTE_MIRROR) {
+            t = 1.0 - abs(mod(t, 2.0) - 1.0);
+        } else if (palette_repeat == PALETTE_CLAMP) {
+            t = clamp(t, 0.0, 1.0);
+        } else {
+            t = fract(t);
+        }
+        color = lookup(palette, t);
+    } else {
+        float s = 3.14159 * t;
+        color = vec4(0.5 * sin(s) + 0.5, 0.5 * sin(s + 2.094) + 0.5, 0.5 * sin(s + 4.188) + 0.5, 1.0);
+    }
+}
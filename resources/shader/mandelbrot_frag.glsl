@@ -16,35 +16,637 @@ along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 #version 330 core
 
+// precision is selected by the program with DOUBLE_PRECISION or EMULATED_DOUBLE
+#if defined(DOUBLE_PRECISION)
+#extension GL_ARB_gpu_shader_fp64 : require
+#define real double
+#define real2 dvec2
+#define real3 dvec3
+#define real_mat3 dmat3
+#else
+#define real float
+#define real2 vec2
+#define real3 vec3
+#define real_mat3 mat3
+#endif
+
 in vec2 tex_coord;
-out vec4 color;
+layout(location = 0) out vec4 color;
 
-uniform mat3 mvp;
 uniform int max_iter;
 
-int calc_pixel(vec3 coord) {
-    float x_0 = coord.x;
-    float y_0 = coord.y;
+// Julia set of the fixed point c instead of the Mandelbrot set
+uniform bool julia;
+
+// formula of the iteration, ids as in fractal.rs
+#define FRACTAL_MANDELBROT 0
+#define FRACTAL_MULTIBROT 1
+#define FRACTAL_BURNING_SHIP 2
+#define FRACTAL_TRICORN 3
+#define FRACTAL_CELTIC 4
+// root finding of the polynomial with the given roots, colored by the root the point ends up at
+#define FRACTAL_NEWTON 5
+// the Newton method plus c
+#define FRACTAL_NOVA 6
+uniform int fractal;
+// d of the Multibrot set z^d + c
+uniform float power;
+
+// has to match MAX_ROOTS in newton.rs
+#define MAX_ROOTS 8
+uniform int root_count;
+uniform vec2 roots[MAX_ROOTS];
+// step length of the Newton method
+uniform float relaxation;
+
+// coloring of the escape time, ids as in coloring.rs
+#define COLORING_BANDED 0
+#define COLORING_SMOOTH 1
+// writes the raw count for histogram.rs to the float target, -1 for points that never escaped
+#define COLORING_HISTOGRAM 2
+// darkens the colors close to the boundary, by the distance estimated from the derivative
+#define COLORING_DISTANCE 3
+#define COLORING_TRAP 4
+uniform int coloring;
+uniform float escape_radius;
+
+// color of the points that never escape, ids as in coloring.rs
+#define INTERIOR_FLAT 0
+// by the period of the cycle their orbit falls into
+#define INTERIOR_PERIOD 1
+uniform int interior;
+
+// orbit trap shapes, ids as in trap.rs
+#define TRAP_POINT 0
+#define TRAP_LINE 1
+#define TRAP_CROSS 2
+#define TRAP_CIRCLE 3
+#define TRAP_IMAGE 4
+uniform int trap_shape;
+uniform vec2 trap_center;
+uniform float trap_size;
+// unit vector along the line, the first arm of the cross and the width of the image
+uniform vec2 trap_direction;
+uniform sampler2D trap_image;
+
+// closest the orbit got to the trap, and the first opaque image pixel it went through
+float trap_distance = 1e30;
+vec4 trap_hit = vec4(0.0);
+
+void trap_orbit(vec2 z) {
+    vec2 p = z - trap_center;
+    float along = dot(p, trap_direction);
+    float across = dot(p, vec2(-trap_direction.y, trap_direction.x));
+    if (trap_shape == TRAP_IMAGE) {
+        if (trap_hit.a > 0.5) {
+            return;
+        }
+        vec2 size = vec2(textureSize(trap_image, 0));
+        vec2 uv = vec2(along, across) / (trap_size * vec2(1.0, size.y / size.x)) * 0.5 + 0.5;
+        if (all(greaterThanEqual(uv, vec2(0.0))) && all(lessThan(uv, vec2(1.0)))) {
+            // the first row of the image is its top
+            vec4 texel = texelFetch(trap_image, min(ivec2(uv.x * size.x, (1.0 - uv.y) * size.y), ivec2(size) - 1), 0);
+            if (texel.a > 0.5) {
+                trap_hit = vec4(texel.rgb, 1.0);
+            }
+        }
+        return;
+    }
+    float d;
+    if (trap_shape == TRAP_POINT) {
+        d = length(p);
+    } else if (trap_shape == TRAP_LINE) {
+        d = abs(across);
+    } else if (trap_shape == TRAP_CROSS) {
+        d = min(abs(along), abs(across));
+    } else {
+        d = abs(length(p) - trap_size);
+    }
+    trap_distance = min(trap_distance, d);
+}
+
+// supersampling, patterns as in sampling.rs
+#define SAMPLE_GRID 0
+#define SAMPLE_ROTATED 1
+#define SAMPLE_JITTERED 2
+uniform int sample_pattern;
+// sample_size x sample_size samples per pixel
+uniform int sample_size;
+
+// integer hash for the jitter, the same as in sampling.rs
+uint hash(uint x) {
+    x ^= x >> 16;
+    x *= 0x7feb352du;
+    x ^= x >> 15;
+    x *= 0x846ca68bu;
+    x ^= x >> 16;
+    return x;
+}
+
+// position of sample i of n x n inside the pixel, -0.5 to 0.5 from its center, as Supersampling::offsets
+vec2 sample_offset(int i, int n) {
+    vec2 cell = vec2(i % n, i / n);
+    if (sample_pattern == SAMPLE_JITTERED) {
+        uint h = hash(uint(gl_FragCoord.x) + hash(uint(gl_FragCoord.y) + hash(uint(i))));
+        return (cell + vec2(float(h), float(hash(h))) / 4294967296.0) / float(n) - 0.5;
+    }
+    vec2 g = (cell + 0.5) / float(n) - 0.5;
+    if (sample_pattern == SAMPLE_ROTATED) {
+        // rotated and scaled back, the 2 x 2 grid becomes the usual (1/8, 3/8) pattern
+        // the corners of the larger grids stick out of the pixel, they wrap around to the other side
+        return fract(vec2(g.x - 0.5 * g.y, 0.5 * g.x + g.y) + 0.5) - 0.5;
+    }
+    return g;
+}
+
+// iteration continuation, the orbits go on from where the previous pass stopped them, see progressive.rs
+uniform bool resume;
+// z as (x hi, x lo, y hi, y lo)
+uniform sampler2D state_z;
+// iterations done, |dz|, the closest the orbit got to the trap, and the period of its cycle, 0 if none
+// the iterations are -1 where there is no orbit to continue
+uniform sampler2D state_orbit;
+layout(location = 1) out vec4 next_z;
+layout(location = 2) out vec4 next_orbit;
+
+void save_orbit(vec4 z, int iter, float dr, int period) {
+    next_z = z;
+    next_orbit = vec4(float(iter), dr, trap_distance, float(period));
+}
+
+// gradient lookup instead of the sine colors, repeat modes as in palette.rs
+#define PALETTE_REPEAT 0
+#define PALETTE_MIRROR 1
+#define PALETTE_CLAMP 2
+uniform bool use_palette;
+uniform sampler2D palette;
+uniform float palette_scale;
+uniform float palette_offset;
+uniform int palette_repeat;
+
+bool integer_power() {
+    return power == floor(power) && power >= 1.0 && power <= 16.0;
+}
+
+// z^power for the powers that are not whole numbers, always in single precision
+vec2 polar_power(vec2 z) {
+    float r = pow(length(z), power);
+    float theta = atan(z.y, z.x) * power;
+    return vec2(r * cos(theta), r * sin(theta));
+}
+
+// the running derivative |dz| for the next iteration, by c or by z_0 for the Julia set
+// only the length is followed, so it works for the formulas that are not analytic too
+float derivative(float r, float dr) {
+    float growth = r > 0.0 ? power * pow(r, power - 1.0) : 0.0;
+    return growth * dr + (julia ? 0.0 : 1.0);
+}
+
+#if defined(EMULATED_DOUBLE)
+// view center as (x hi, x lo, y hi, y lo) and the linear part of mvp
+uniform vec4 center;
+uniform mat2 scale;
+uniform vec4 c;
+
+// double-float arithmetic, a value is the unevaluated sum hi + lo of two floats
+vec2 df_add(vec2 a, vec2 b) {
+    float s = a.x + b.x;
+    float v = s - a.x;
+    float e = (a.x - (s - v)) + (b.x - v) + a.y + b.y;
+    float hi = s + e;
+    return vec2(hi, e - (hi - s));
+}
+
+vec2 df_split(float a) {
+    float t = a * 4097.0;
+    float hi = t - (t - a);
+    return vec2(hi, a - hi);
+}
+
+vec2 df_mul(vec2 a, vec2 b) {
+    float p = a.x * b.x;
+    vec2 sa = df_split(a.x);
+    vec2 sb = df_split(b.x);
+    float e = ((sa.x * sb.x - p) + sa.x * sb.y + sa.y * sb.x) + sa.y * sb.y;
+    e += a.x * b.y + a.y * b.x;
+    float hi = p + e;
+    return vec2(hi, e - (hi - p));
+}
+
+vec2 df_abs(vec2 a) {
+    return a.x < 0.0 ? -a : a;
+}
+
+// z^power for the Multibrot set, z = x + iy
+void df_multibrot(inout vec2 x, inout vec2 y) {
+    if (integer_power()) {
+        vec2 r_x = x, r_y = y;
+        for (int i = 1; i < int(power); i++) {
+            vec2 t = df_add(df_mul(r_x, x), -df_mul(r_y, y));
+            r_y = df_add(df_mul(r_x, y), df_mul(r_y, x));
+            r_x = t;
+        }
+        x = r_x;
+        y = r_y;
+    } else {
+        vec2 z = polar_power(vec2(x.x, y.x));
+        x = vec2(z.x, 0.0);
+        y = vec2(z.y, 0.0);
+    }
+}
+
+// the main cardioid and the period 2 bulb of the Mandelbrot set are inside, no need to iterate them
+int df_known_period(vec2 x_0, vec2 y_0) {
+    if (fractal != FRACTAL_MANDELBROT || julia) {
+        return 0;
+    }
+    vec2 a = df_add(x_0, vec2(-0.25, 0.0));
+    vec2 y_2 = df_mul(y_0, y_0);
+    vec2 q = df_add(df_mul(a, a), y_2);
+    if (df_add(df_mul(q, df_add(q, a)), -0.25 * y_2).x <= 0.0) {
+        return 1;
+    }
+    vec2 b = df_add(x_0, vec2(1.0, 0.0));
+    if (df_add(df_mul(b, b), y_2).x <= 0.0625) {
+        return 2;
+    }
+    return 0;
+}
+
+// an orbit that comes back within tolerance of an earlier point is in a cycle and never escapes
+int calc_pixel(vec2 x_0, vec2 y_0, float tolerance, out float z_2, out float dr, out int period) {
+    int iter = 0;
+    dr = julia ? 1.0 : 0.0;
+    z_2 = 0.0;
+    period = df_known_period(x_0, y_0);
+    // the trap still needs the orbit
+    if (period > 0 && coloring != COLORING_TRAP) {
+        save_orbit(vec4(0.0), 0, dr, period);
+        return max_iter;
+    }
+    vec2 x = vec2(0.0), y = vec2(0.0);
+    if (julia) {
+        x = x_0;
+        y = y_0;
+        x_0 = c.xy;
+        y_0 = c.zw;
+    }
+    vec4 orbit = texelFetch(state_orbit, ivec2(gl_FragCoord.xy), 0);
+    // the pixels that scrolled into view since the previous pass start from the beginning
+    if (resume && orbit.x >= 0.0) {
+        vec4 saved = texelFetch(state_z, ivec2(gl_FragCoord.xy), 0);
+        x = saved.xy;
+        y = saved.zw;
+        iter = int(orbit.x);
+        dr = orbit.y;
+        trap_distance = orbit.z;
+        period = int(orbit.w);
+        if (period > 0) {
+            save_orbit(saved, iter, dr, period);
+            return max_iter;
+        }
+    }
+    vec2 x_2 = df_mul(x, x);
+    vec2 y_2 = df_mul(y, y);
+    // Brent's cycle detection, the saved point moves ahead after 1, 2, 4, ... iterations
+    vec2 saved_x = x, saved_y = y;
+    int steps = 0, check = 1;
+    while (x_2.x + y_2.x <= escape_radius * escape_radius && iter < max_iter) {
+        if (coloring == COLORING_DISTANCE) {
+            dr = derivative(sqrt(x_2.x + y_2.x), dr);
+        }
+        if (fractal == FRACTAL_MULTIBROT) {
+            df_multibrot(x, y);
+            x = df_add(x, x_0);
+            y = df_add(y, y_0);
+        } else {
+            vec2 xy = df_mul(2.0 * x, y);
+            vec2 re = df_add(x_2, -y_2);
+            if (fractal == FRACTAL_BURNING_SHIP) {
+                xy = df_abs(xy);
+            } else if (fractal == FRACTAL_TRICORN) {
+                xy = -xy;
+            } else if (fractal == FRACTAL_CELTIC) {
+                re = df_abs(re);
+            }
+            y = df_add(xy, y_0);
+            x = df_add(re, x_0);
+        }
+        x_2 = df_mul(x, x);
+        y_2 = df_mul(y, y);
+        iter++;
+        if (coloring == COLORING_TRAP) {
+            trap_orbit(vec2(x.x, y.x));
+        }
+        steps++;
+        if (abs(df_add(x, -saved_x).x) + abs(df_add(y, -saved_y).x) < tolerance) {
+            period = steps;
+            save_orbit(vec4(x, y), iter, dr, period);
+            return max_iter;
+        }
+        if (steps == check) {
+            saved_x = x;
+            saved_y = y;
+            steps = 0;
+            check *= 2;
+        }
+    }
+    z_2 = x_2.x + y_2.x;
+    save_orbit(vec4(x, y), iter, dr, 0);
+    return iter;
+}
+#else
+uniform real_mat3 mvp;
+uniform real2 c;
+
+real2 multibrot(real2 z) {
+    if (integer_power()) {
+        real2 result = z;
+        for (int i = 1; i < int(power); i++) {
+            result = real2(result.x * z.x - result.y * z.y, result.x * z.y + result.y * z.x);
+        }
+        return result;
+    }
+    return real2(polar_power(vec2(z)));
+}
+
+// z for the state texture, the double is split into two floats
+vec4 split_z(real x, real y) {
+    float x_hi = float(x);
+    float y_hi = float(y);
+    return vec4(x_hi, float(x - real(x_hi)), y_hi, float(y - real(y_hi)));
+}
+
+// the main cardioid and the period 2 bulb of the Mandelbrot set are inside, no need to iterate them
+int known_period(real x_0, real y_0) {
+    if (fractal != FRACTAL_MANDELBROT || julia) {
+        return 0;
+    }
+    real a = x_0 - 0.25;
+    real q = a * a + y_0 * y_0;
+    if (q * (q + a) <= 0.25 * y_0 * y_0) {
+        return 1;
+    }
+    if ((x_0 + 1.0) * (x_0 + 1.0) + y_0 * y_0 <= 0.0625) {
+        return 2;
+    }
+    return 0;
+}
+
+// an orbit that comes back within tolerance of an earlier point is in a cycle and never escapes
+int calc_pixel(real3 coord, float tolerance, out float z_2, out float dr, out int period) {
+    real x_0 = coord.x;
+    real y_0 = coord.y;
     int iter = 0;
-    float x = 0.0, y = 0.0;
-    float x_2 = 0.0;
-    float y_2 = 0.0;
-    while (x_2 + y_2 <= 4 && iter < max_iter) {
-        y = 2.0 * x * y + y_0;
-        x = x_2 - y_2 + x_0;
+    dr = julia ? 1.0 : 0.0;
+    z_2 = 0.0;
+    period = known_period(x_0, y_0);
+    // the trap still needs the orbit
+    if (period > 0 && coloring != COLORING_TRAP) {
+        save_orbit(vec4(0.0), 0, dr, period);
+        return max_iter;
+    }
+    real x = 0.0, y = 0.0;
+    if (julia) {
+        x = x_0;
+        y = y_0;
+        x_0 = c.x;
+        y_0 = c.y;
+    }
+    vec4 orbit = texelFetch(state_orbit, ivec2(gl_FragCoord.xy), 0);
+    // the pixels that scrolled into view since the previous pass start from the beginning
+    if (resume && orbit.x >= 0.0) {
+        vec4 saved = texelFetch(state_z, ivec2(gl_FragCoord.xy), 0);
+        x = real(saved.x) + real(saved.y);
+        y = real(saved.z) + real(saved.w);
+        iter = int(orbit.x);
+        dr = orbit.y;
+        trap_distance = orbit.z;
+        period = int(orbit.w);
+        if (period > 0) {
+            save_orbit(saved, iter, dr, period);
+            return max_iter;
+        }
+    }
+    real x_2 = x * x;
+    real y_2 = y * y;
+    // Brent's cycle detection, the saved point moves ahead after 1, 2, 4, ... iterations
+    real saved_x = x, saved_y = y;
+    int steps = 0, check = 1;
+    while (x_2 + y_2 <= escape_radius * escape_radius && iter < max_iter) {
+        if (coloring == COLORING_DISTANCE) {
+            dr = derivative(sqrt(float(x_2 + y_2)), dr);
+        }
+        if (fractal == FRACTAL_MULTIBROT) {
+            real2 z = multibrot(real2(x, y));
+            x = z.x + x_0;
+            y = z.y + y_0;
+        } else {
+            real xy = 2.0 * x * y;
+            real re = x_2 - y_2;
+            if (fractal == FRACTAL_BURNING_SHIP) {
+                xy = abs(xy);
+            } else if (fractal == FRACTAL_TRICORN) {
+                xy = -xy;
+            } else if (fractal == FRACTAL_CELTIC) {
+                re = abs(re);
+            }
+            y = xy + y_0;
+            x = re + x_0;
+        }
         x_2 = x * x;
         y_2 = y *y;
         iter++;
+        if (coloring == COLORING_TRAP) {
+            trap_orbit(vec2(float(x), float(y)));
+        }
+        steps++;
+        if (abs(x - saved_x) + abs(y - saved_y) < tolerance) {
+            period = steps;
+            save_orbit(split_z(x, y), iter, dr, period);
+            return max_iter;
+        }
+        if (steps == check) {
+            saved_x = x;
+            saved_y = y;
+            steps = 0;
+            check *= 2;
+        }
     }
+    z_2 = float(x_2 + y_2);
+    save_orbit(split_z(x, y), iter, dr, 0);
     return iter;
 }
+#endif
 
-void main() {
-    vec3 corrected = mvp * vec3(tex_coord, 1.0);
-    int iters = calc_pixel(corrected);
+vec4 palette_color(float n) {
+    float t = n / palette_scale + palette_offset;
+    if (palette_repeat == PALETTE_MIRROR) {
+        t = 1.0 - abs(mod(t, 2.0) - 1.0);
+    } else if (palette_repeat == PALETTE_CLAMP) {
+        t = clamp(t, 0.0, 1.0);
+    } else {
+        t = fract(t);
+    }
+    // between the first and the last texel centers, like the table on the cpu
+    float size = float(textureSize(palette, 0).x);
+    return texture(palette, vec2((t * (size - 1.0) + 0.5) / size, 0.5));
+}
+
+// the palette or the sine colors
+vec4 base_color(float n) {
+    if (use_palette) {
+        return palette_color(n);
+    }
+    return vec4(0.5 * sin(n) + 0.5, 0.5 * sin(n + 2.094) + 0.5, 0.5 * sin(n + 4.188) + 0.5, 1.0);
+}
+
+// periods spread by the golden ratio, so that close ones still get different colors
+// black where no cycle was found
+vec4 period_color(int period) {
+    if (period == 0) {
+        return vec4(0.0, 0.0, 0.0, 1.0);
+    }
+    float t = fract(0.618034 * float(period));
+    return base_color(t * (use_palette ? palette_scale : 6.283185));
+}
+
+// index of the root p converges to, -1 if it doesn't within max_iter, as Polynomial::newton
+// p is z_0 of the Newton fractal and c of the Nova fractal, unless the Julia set gives c
+int newton_root(real2 p, real2 julia_c, out int steps) {
+    bool nova = fractal == FRACTAL_NOVA;
+    real2 z = p;
+    real2 add = real2(0.0);
+    if (nova && julia) {
+        add = julia_c;
+    } else if (nova) {
+        // the roots are critical points of the Newton step, the first iteration from roots[0] only adds c
+        z = real2(roots[0]) + p;
+        add = p;
+    }
+    for (steps = 0; steps < max_iter; steps++) {
+        // p'/p = sum 1 / (z - root)
+        real2 sum = real2(0.0);
+        for (int i = 0; i < root_count; i++) {
+            real2 d = z - real2(roots[i]);
+            real len = dot(d, d);
+            if (!nova && len < 1e-10) {
+                return i;
+            }
+            sum += real2(d.x, -d.y) / len;
+        }
+        real2 next = z - real2(sum.x, -sum.y) / dot(sum, sum) * real(relaxation) + add;
+        // the Nova fractal settles on fixed points that depend on c, colored by the closest root
+        if (nova && dot(next - z, next - z) < 1e-10) {
+            int nearest = 0;
+            for (int i = 1; i < root_count; i++) {
+                if (distance(vec2(next), roots[i]) < distance(vec2(next), roots[nearest])) {
+                    nearest = i;
+                }
+            }
+            return nearest;
+        }
+        z = next;
+    }
+    return -1;
+}
+
+// every root gets its own color, darker the more steps it took to get there
+vec4 newton_color(int root, int steps) {
+    if (root < 0) {
+        return vec4(0.0, 0.0, 0.0, 1.0);
+    }
+    float t = float(root) / float(root_count);
+    vec4 base = base_color(t * (use_palette ? palette_scale : 6.283185));
+    return vec4(base.rgb / (1.0 + 0.05 * float(steps)), 1.0);
+}
+
+// de is |dz| times the size of a pixel, for the distance estimation
+// period is the length of the cycle of the points that never escape, 0 if unknown
+vec4 pixel_color(int iters, float z_2, float de, int period) {
+    if (coloring == COLORING_TRAP) {
+        if (trap_shape != TRAP_IMAGE) {
+            return base_color(4.0 * log2(trap_size / max(trap_distance, 1e-30)));
+        }
+        // the orbits that missed the image get the smooth colors
+        if (trap_hit.a > 0.5) {
+            return trap_hit;
+        }
+    }
+    if (iters >= max_iter && interior == INTERIOR_PERIOD && coloring != COLORING_HISTOGRAM) {
+        return period_color(period);
+    }
     // float scale = float(iters) / float(max_iter);
     // color = vec4(scale, 1.0 - scale, 0.5, 1.0);
     // ---
     float n = float(iters);
-    color = vec4(0.5 * sin(n) + 0.5, 0.5 * sin(n + 2.094) + 0.5, 0.5 * sin(n + 4.188) + 0.5, 1.0);
+    if (coloring != COLORING_BANDED && iters < max_iter) {
+        // normalized iteration count, log-log of |z| at the escape
+        n += 1.0 - log(0.5 * log(z_2)) / log(max(power, 1.0001));
+    }
+    if (coloring == COLORING_HISTOGRAM) {
+        return vec4(iters < max_iter ? n : -1.0, 0.0, 0.0, 1.0);
+    }
+    vec4 base = base_color(n);
+    if (coloring == COLORING_DISTANCE) {
+        if (iters >= max_iter) {
+            return vec4(0.0, 0.0, 0.0, 1.0);
+        }
+        // 0.5 |z| ln|z| / |dz| in pixels, the boundary fades to black within about a pixel
+        float distance = 0.25 * sqrt(z_2) * log(z_2) / de;
+        return vec4(base.rgb * sqrt(clamp(distance, 0.0, 1.0)), 1.0);
+    }
+    return base;
+}
+
+// color of the point at coord, one sample of the pixel
+// step is the size of a pixel in tex_coord
+vec4 sample_color(vec2 coord, float step) {
+    // every sample follows its own orbit
+    trap_distance = 1e30;
+    trap_hit = vec4(0.0);
+    float z_2;
+    float dr;
+    int period;
+    if (fractal == FRACTAL_NEWTON || fractal == FRACTAL_NOVA) {
+        int steps;
+#if defined(EMULATED_DOUBLE)
+        // single precision is enough, the roots are floats anyway
+        vec2 p = vec2(center.x + center.y, center.z + center.w) + scale * coord;
+        int root = newton_root(p, vec2(c.x + c.y, c.z + c.w), steps);
+#else
+        int root = newton_root((mvp * real3(coord, 1.0)).xy, c, steps);
+#endif
+        return newton_color(root, steps);
+    }
+#if defined(EMULATED_DOUBLE)
+    float pixel = length(scale[1]) * step;
+    // the offset from the center is small, so single precision is enough for it
+    vec2 offset = scale * coord;
+    int iters = calc_pixel(df_add(center.xy, vec2(offset.x, 0.0)), df_add(center.zw, vec2(offset.y, 0.0)), pixel / 1024.0, z_2, dr, period);
+#else
+    float pixel = float(length(mvp[1].xy)) * step;
+    real3 corrected = mvp * real3(coord, 1.0);
+    int iters = calc_pixel(corrected, pixel / 1024.0, z_2, dr, period);
+#endif
+    return pixel_color(iters, z_2, dr * pixel, period);
+}
+
+void main() {
+    // tex_coord changes by 2 / width and 2 / height from one pixel to the next
+    vec2 texel = vec2(abs(dFdx(tex_coord.x)), abs(dFdy(tex_coord.y)));
+    // the raw counts of the histogram mode can't be averaged
+    bool counts = coloring == COLORING_HISTOGRAM && fractal != FRACTAL_NEWTON && fractal != FRACTAL_NOVA;
+    int n = counts ? 1 : max(sample_size, 1);
+    if (n == 1) {
+        color = sample_color(tex_coord, texel.y);
+        return;
+    }
+    vec3 sum = vec3(0.0);
+    for (int i = 0; i < n * n; i++) {
+        vec4 value = sample_color(tex_coord + sample_offset(i, n) * texel, texel.y);
+        // the colors are sRGB, they are averaged in linear color
+        sum += pow(value.rgb, vec3(2.2));
+    }
+    color = vec4(pow(sum / float(n * n), vec3(1.0 / 2.2)), 1.0);
 }
@@ -0,0 +1,330 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+#version 330 core
+
+// width of the reference orbit texture, the orbit continues on the next row
+#define ORBIT_WIDTH 1024
+
+in vec2 tex_coord;
+out vec4 color;
+
+uniform int max_iter;
+
+// Julia set, the reference orbit then starts from the reference point instead of c
+uniform bool julia;
+
+// coloring of the escape time, ids as in coloring.rs
+#define COLORING_BANDED 0
+#define COLORING_SMOOTH 1
+// writes the raw count for histogram.rs to the float target, -1 for points that never escaped
+#define COLORING_HISTOGRAM 2
+// darkens the colors close to the boundary, by the distance estimated from the derivative
+#define COLORING_DISTANCE 3
+#define COLORING_TRAP 4
+uniform int coloring;
+uniform float escape_radius;
+
+// color of the points that never escape, ids as in coloring.rs
+// no cycle detection here, the float orbit is not accurate enough for it at these depths
+#define INTERIOR_FLAT 0
+#define INTERIOR_PERIOD 1
+uniform int interior;
+
+// orbit trap shapes, ids as in trap.rs
+#define TRAP_POINT 0
+#define TRAP_LINE 1
+#define TRAP_CROSS 2
+#define TRAP_CIRCLE 3
+#define TRAP_IMAGE 4
+uniform int trap_shape;
+uniform vec2 trap_center;
+uniform float trap_size;
+// unit vector along the line, the first arm of the cross and the width of the image
+uniform vec2 trap_direction;
+uniform sampler2D trap_image;
+
+// closest the orbit got to the trap, and the first opaque image pixel it went through
+float trap_distance = 1e30;
+vec4 trap_hit = vec4(0.0);
+
+void trap_orbit(vec2 z) {
+    vec2 p = z - trap_center;
+    float along = dot(p, trap_direction);
+    float across = dot(p, vec2(-trap_direction.y, trap_direction.x));
+    if (trap_shape == TRAP_IMAGE) {
+        if (trap_hit.a > 0.5) {
+            return;
+        }
+        vec2 size = vec2(textureSize(trap_image, 0));
+        vec2 uv = vec2(along, across) / (trap_size * vec2(1.0, size.y / size.x)) * 0.5 + 0.5;
+        if (all(greaterThanEqual(uv, vec2(0.0))) && all(lessThan(uv, vec2(1.0)))) {
+            // the first row of the image is its top
+            vec4 texel = texelFetch(trap_image, min(ivec2(uv.x * size.x, (1.0 - uv.y) * size.y), ivec2(size) - 1), 0);
+            if (texel.a > 0.5) {
+                trap_hit = vec4(texel.rgb, 1.0);
+            }
+        }
+        return;
+    }
+    float d;
+    if (trap_shape == TRAP_POINT) {
+        d = length(p);
+    } else if (trap_shape == TRAP_LINE) {
+        d = abs(across);
+    } else if (trap_shape == TRAP_CROSS) {
+        d = min(abs(along), abs(across));
+    } else {
+        d = abs(length(p) - trap_size);
+    }
+    trap_distance = min(trap_distance, d);
+}
+
+// supersampling, patterns as in sampling.rs
+#define SAMPLE_GRID 0
+#define SAMPLE_ROTATED 1
+#define SAMPLE_JITTERED 2
+uniform int sample_pattern;
+// sample_size x sample_size samples per pixel
+uniform int sample_size;
+
+// integer hash for the jitter, the same as in sampling.rs
+uint hash(uint x) {
+    x ^= x >> 16;
+    x *= 0x7feb352du;
+    x ^= x >> 15;
+    x *= 0x846ca68bu;
+    x ^= x >> 16;
+    return x;
+}
+
+// position of sample i of n x n inside the pixel, -0.5 to 0.5 from its center, as Supersampling::offsets
+vec2 sample_offset(int i, int n) {
+    vec2 cell = vec2(i % n, i / n);
+    if (sample_pattern == SAMPLE_JITTERED) {
+        uint h = hash(uint(gl_FragCoord.x) + hash(uint(gl_FragCoord.y) + hash(uint(i))));
+        return (cell + vec2(float(h), float(hash(h))) / 4294967296.0) / float(n) - 0.5;
+    }
+    vec2 g = (cell + 0.5) / float(n) - 0.5;
+    if (sample_pattern == SAMPLE_ROTATED) {
+        // rotated and scaled back, the 2 x 2 grid becomes the usual (1/8, 3/8) pattern
+        // the corners of the larger grids stick out of the pixel, they wrap around to the other side
+        return fract(vec2(g.x - 0.5 * g.y, 0.5 * g.x + g.y) + 0.5) - 0.5;
+    }
+    return g;
+}
+
+// gradient lookup instead of the sine colors, repeat modes as in palette.rs
+#define PALETTE_REPEAT 0
+#define PALETTE_MIRROR 1
+#define PALETTE_CLAMP 2
+uniform bool use_palette;
+uniform sampler2D palette;
+uniform float palette_scale;
+uniform float palette_offset;
+uniform int palette_repeat;
+
+// Z_0 ... Z_(orbit_length - 1) of the reference point, computed on the CPU in high precision
+uniform sampler2D orbit;
+uniform int orbit_length;
+
+// pixel offset from the reference point is (scale * tex_coord + offset) * 2^delta_exponent
+uniform mat2 scale;
+uniform vec2 offset;
+uniform int delta_exponent;
+
+vec2 reference(int n) {
+    return texelFetch(orbit, ivec2(n % ORBIT_WIDTH, n / ORBIT_WIDTH), 0).xy;
+}
+
+vec2 complex_mul(vec2 a, vec2 b) {
+    return vec2(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+// 2^e in two steps, so that neither factor leaves the normal float range
+vec2 scaled(vec2 d, int e) {
+    return d * exp2(float(e / 2)) * exp2(float(e - e / 2));
+}
+
+// iterates the difference from the reference orbit, -1 for glitched pixels
+// the derivative |dz| comes out as dr * 2^dr_e, it can get far too large for a float
+int calc_pixel(vec2 d_0, out float z_2, out float dr, out int dr_e) {
+    // z_0 = 0 for the Mandelbrot set, the pixel itself for the Julia set
+    vec2 d = julia ? d_0 : vec2(0.0);
+    vec2 d_c = julia ? vec2(0.0) : d_0;
+    int e = delta_exponent;
+    // while the delta is too small for a float it is kept as d * 2^e
+    bool rescaled = true;
+    int iter = 0;
+    z_2 = 0.0;
+    dr = julia ? 1.0 : 0.0;
+    dr_e = 0;
+    while (iter < max_iter) {
+        if (iter + 1 >= orbit_length) {
+            // the reference escaped before this pixel did
+            return -1;
+        }
+        vec2 z = reference(iter);
+        if (coloring == COLORING_DISTANCE) {
+            // dz = 2 z dz + 1, the 1 shrinks with the exponent and is lost in the end
+            dr = 2.0 * length(rescaled ? z : z + d) * dr + (julia ? 0.0 : exp2(float(-dr_e)));
+            if (dr > 4294967296.0) {
+                dr /= 4294967296.0;
+                dr_e += 32;
+            }
+        }
+        if (rescaled) {
+            d = 2.0 * complex_mul(z, d) + complex_mul(scaled(d, e), d) + d_c;
+            float size = max(abs(d.x), abs(d.y));
+            if (size > 4294967296.0) {
+                d /= 4294967296.0;
+                d_c /= 4294967296.0;
+                size /= 4294967296.0;
+                e += 32;
+            }
+            if (float(e) + log2(size) > -100.0) {
+                d = scaled(d, e);
+                d_c = scaled(d_c, e);
+                rescaled = false;
+            }
+        } else {
+            d = 2.0 * complex_mul(z, d) + complex_mul(d, d) + d_c;
+        }
+        iter++;
+        vec2 z_next = reference(iter);
+        vec2 full = rescaled ? z_next : z_next + d;
+        float full_2 = dot(full, full);
+        z_2 = full_2;
+        if (coloring == COLORING_TRAP) {
+            trap_orbit(full);
+        }
+        if (full_2 > escape_radius * escape_radius) {
+            break;
+        }
+        // the pixel got much closer to zero than the reference, the delta lost its precision
+        if (!rescaled && full_2 < 1e-6 * dot(z_next, z_next)) {
+            return -1;
+        }
+    }
+    return iter;
+}
+
+vec4 palette_color(float n) {
+    float t = n / palette_scale + palette_offset;
+    if (palette_repeat == PALETTE_MIRROR) {
+        t = 1.0 - abs(mod(t, 2.0) - 1.0);
+    } else if (palette_repeat == PALETTE_CLAMP) {
+        t = clamp(t, 0.0, 1.0);
+    } else {
+        t = fract(t);
+    }
+    // between the first and the last texel centers, like the table on the cpu
+    float size = float(textureSize(palette, 0).x);
+    return texture(palette, vec2((t * (size - 1.0) + 0.5) / size, 0.5));
+}
+
+// the palette or the sine colors
+vec4 base_color(float n) {
+    if (use_palette) {
+        return palette_color(n);
+    }
+    return vec4(0.5 * sin(n) + 0.5, 0.5 * sin(n + 2.094) + 0.5, 0.5 * sin(n + 4.188) + 0.5, 1.0);
+}
+
+// periods spread by the golden ratio, so that close ones still get different colors
+// black where no cycle was found
+vec4 period_color(int period) {
+    if (period == 0) {
+        return vec4(0.0, 0.0, 0.0, 1.0);
+    }
+    float t = fract(0.618034 * float(period));
+    return base_color(t * (use_palette ? palette_scale : 6.283185));
+}
+
+// de is |dz| times the size of a pixel, for the distance estimation
+// period is the length of the cycle of the points that never escape, 0 if unknown
+vec4 pixel_color(int iters, float z_2, float de, int period) {
+    if (coloring == COLORING_TRAP) {
+        if (trap_shape != TRAP_IMAGE) {
+            return base_color(4.0 * log2(trap_size / max(trap_distance, 1e-30)));
+        }
+        // the orbits that missed the image get the smooth colors
+        if (trap_hit.a > 0.5) {
+            return trap_hit;
+        }
+    }
+    if (iters >= max_iter && interior == INTERIOR_PERIOD && coloring != COLORING_HISTOGRAM) {
+        return period_color(period);
+    }
+    float n = float(iters);
+    if (coloring != COLORING_BANDED && iters < max_iter) {
+        // normalized iteration count of z^2 + c
+        n += 1.0 - log(0.5 * log(z_2)) / log(2.0);
+    }
+    if (coloring == COLORING_HISTOGRAM) {
+        return vec4(iters < max_iter ? n : -1.0, 0.0, 0.0, 1.0);
+    }
+    vec4 base = base_color(n);
+    if (coloring == COLORING_DISTANCE) {
+        if (iters >= max_iter) {
+            return vec4(0.0, 0.0, 0.0, 1.0);
+        }
+        // 0.5 |z| ln|z| / |dz| in pixels, the boundary fades to black within about a pixel
+        float distance = 0.25 * sqrt(z_2) * log(z_2) / de;
+        return vec4(base.rgb * sqrt(clamp(distance, 0.0, 1.0)), 1.0);
+    }
+    return base;
+}
+
+// color of the point at coord, one sample of the pixel, false where the delta lost its precision
+// pixel is the size of a pixel in 2^delta_exponent
+bool sample_color(vec2 coord, float pixel, out vec4 result) {
+    // every sample follows its own orbit
+    trap_distance = 1e30;
+    trap_hit = vec4(0.0);
+    float z_2;
+    float dr;
+    int dr_e;
+    int iters = calc_pixel(scale * coord + offset, z_2, dr, dr_e);
+    if (iters < 0) {
+        return false;
+    }
+    result = pixel_color(iters, z_2, scaled(vec2(dr * pixel, 0.0), dr_e + delta_exponent).x, 0);
+    return true;
+}
+
+void main() {
+    // tex_coord changes by 2 / width and 2 / height from one pixel to the next
+    vec2 texel = vec2(abs(dFdx(tex_coord.x)), abs(dFdy(tex_coord.y)));
+    float pixel = length(scale[1]) * texel.y;
+    // the raw counts of the histogram mode can't be averaged
+    int n = coloring == COLORING_HISTOGRAM ? 1 : max(sample_size, 1);
+    vec3 sum = vec3(0.0);
+    for (int i = 0; i < n * n; i++) {
+        vec4 value;
+        // the whole pixel is drawn again against the next reference
+        if (!sample_color(tex_coord + (n == 1 ? vec2(0.0) : sample_offset(i, n) * texel), pixel, value)) {
+            discard;
+        }
+        if (n == 1) {
+            color = value;
+            return;
+        }
+        // the colors are sRGB, they are averaged in linear color
+        sum += pow(value.rgb, vec3(2.2));
+    }
+    color = vec4(pow(sum / float(n * n), vec3(1.0 / 2.2)), 1.0);
+}
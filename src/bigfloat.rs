@@ -0,0 +1,491 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::cmp::Ordering;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+const LIMB_BITS: i64 = 32;
+
+// arbitrary precision binary floating point number
+// value = (-1)^negative * 0.mantissa * 2^exponent, the most significant limb comes first
+#[derive(Clone, Debug, PartialEq)]
+pub struct BigFloat {
+    negative: bool,
+    exponent: i64,
+    // always `limbs` long, normalized so that the top bit of the first limb is set, empty for zero
+    mantissa: Vec<u32>,
+    limbs: usize,
+}
+
+// a point of the complex plane with big float coordinates
+#[derive(Clone, Debug, PartialEq)]
+pub struct BigVec2 {
+    pub x: BigFloat,
+    pub y: BigFloat,
+}
+
+impl BigFloat {
+    pub fn zero(limbs: usize) -> Self {
+        Self {
+            negative: false,
+            exponent: 0,
+            mantissa: Vec::new(),
+            limbs: limbs.max(2),
+        }
+    }
+
+    pub fn from_f64(value: f64, limbs: usize) -> Self {
+        if value == 0.0 || !value.is_finite() {
+            return Self::zero(limbs);
+        }
+        let bits = value.abs().to_bits();
+        let biased = ((bits >> 52) & 0x7ff) as i64;
+        let fraction = bits & ((1u64 << 52) - 1);
+        // value = m * 2^e
+        let (m, e) = if biased == 0 {
+            (fraction, -1074)
+        } else {
+            (fraction | (1u64 << 52), biased - 1075)
+        };
+        let shift = m.leading_zeros() as i64;
+        let m = m << shift;
+        let mantissa = vec![(m >> 32) as u32, m as u32];
+        Self::normalized(value < 0.0, e - shift + 64, mantissa, limbs.max(2))
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        if self.is_zero() {
+            return 0.0;
+        }
+        let m = ((self.mantissa[0] as u64) << 32) | self.mantissa[1] as u64;
+        let value = ldexp(m as f64, self.exponent - 64);
+        if self.negative { -value } else { value }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa.is_empty()
+    }
+
+    pub fn limbs(&self) -> usize {
+        self.limbs
+    }
+
+    // precision is counted in 32 bit limbs
+    pub fn set_precision(&mut self, limbs: usize) {
+        self.limbs = limbs.max(2);
+        if !self.is_zero() {
+            self.mantissa.resize(self.limbs, 0);
+        }
+    }
+
+    // multiplication by 2^n
+    pub fn mul_pow2(&self, n: i64) -> Self {
+        let mut result = self.clone();
+        if !result.is_zero() {
+            result.exponent += n;
+        }
+        result
+    }
+
+    // rounded to `decimals` digits after the point, the integer part has to fit in a double
+    pub fn to_decimal(&self, decimals: usize) -> String {
+        let one = BigFloat::from_f64(1.0, self.limbs);
+        let ten = BigFloat::from_f64(10.0, self.limbs);
+        let mut rest = self.clone();
+        rest.negative = false;
+        // half of the last digit, the digits are cut off after it
+        if let Ok(half) = BigFloat::parse_decimal(&format!("5e-{}", decimals + 1), self.limbs) {
+            rest = &rest + &half;
+        }
+        // the double may round up to the next integer, the rest tells
+        let mut integer = rest.to_f64().floor();
+        rest = &rest - &BigFloat::from_f64(integer, self.limbs);
+        if rest.negative {
+            integer -= 1.0;
+            rest = &rest + &one;
+        }
+        let mut digits = String::with_capacity(decimals);
+        for _ in 0..decimals {
+            rest = &rest * &ten;
+            let mut digit = rest.to_f64().floor().clamp(0.0, 9.0);
+            rest = &rest - &BigFloat::from_f64(digit, self.limbs);
+            if rest.negative {
+                digit -= 1.0;
+                rest = &rest + &one;
+            }
+            digits.push(char::from(b'0' + digit as u8));
+        }
+        let digits = digits.trim_end_matches('0');
+        format!("{}{}.{}", if self.negative { "-" } else { "" }, integer, if digits.is_empty() { "0" } else { digits })
+    }
+
+    // a decimal number like -0.745 or 1.5e-20, every digit of it is kept if `limbs` allows
+    pub fn parse_decimal(text: &str, limbs: usize) -> Result<Self, String> {
+        let error = || format!("expected a decimal number: {}", text);
+        let (mantissa, exponent) = match text.split_once(['e', 'E']) {
+            Some((mantissa, exponent)) => (mantissa, exponent.parse::<i64>().map_err(|_| error())?),
+            None => (text, 0),
+        };
+        let (negative, mantissa) = match mantissa.strip_prefix('-') {
+            Some(mantissa) => (true, mantissa),
+            None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+        };
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits: Vec<u8> = integer.bytes().chain(fraction.bytes()).collect();
+        if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) || exponent.abs() > 100_000 {
+            return Err(error());
+        }
+        // the digits as an integer first, in enough limbs to hold it exactly
+        let exact = limbs.max((digits.len() as f64 * 10f64.log2() / LIMB_BITS as f64).ceil() as usize + 1);
+        let mut value = BigFloat::zero(exact);
+        // nine digits fit in a limb
+        for chunk in digits.chunks(9) {
+            let chunk_value = chunk.iter().fold(0u32, |value, digit| value * 10 + (digit - b'0') as u32);
+            value = &(&value * &BigFloat::from_f64(10f64.powi(chunk.len() as i32), exact)) + &BigFloat::from_f64(chunk_value as f64, exact);
+        }
+        // then the power of ten
+        let mut power = exponent - fraction.len() as i64;
+        while power > 0 {
+            let step = power.min(9);
+            value = &value * &BigFloat::from_f64(10f64.powi(step as i32), exact);
+            power -= step;
+        }
+        while power < 0 {
+            let step = (-power).min(9);
+            value = value.div_small(10u32.pow(step as u32));
+            power += step;
+        }
+        value.negative = negative && !value.is_zero();
+        value.set_precision(limbs);
+        Ok(value)
+    }
+
+    // multiplication by numerator / denominator, as exact as the precision allows
+    pub fn mul_ratio(&self, numerator: u32, denominator: u32) -> Self {
+        (self * &BigFloat::from_f64(numerator as f64, self.limbs)).div_small(denominator)
+    }
+
+    // long division of the mantissa with one more limb
+    fn div_small(&self, divisor: u32) -> Self {
+        if self.is_zero() {
+            return self.clone();
+        }
+        let mut quotient = Vec::with_capacity(self.limbs + 1);
+        let mut remainder = 0u64;
+        for limb in self.mantissa.iter().copied().chain(std::iter::once(0)) {
+            let current = (remainder << 32) | limb as u64;
+            quotient.push((current / divisor as u64) as u32);
+            remainder = current % divisor as u64;
+        }
+        Self::normalized(self.negative, self.exponent, quotient, self.limbs)
+    }
+
+    fn normalized(negative: bool, exponent: i64, mantissa: Vec<u32>, limbs: usize) -> Self {
+        let first = match mantissa.iter().position(|limb| *limb != 0) {
+            Some(first) => first,
+            None => return Self::zero(limbs),
+        };
+        let shift = first as i64 * LIMB_BITS + mantissa[first].leading_zeros() as i64;
+        let mut mantissa = shift_left(&mantissa, shift);
+        mantissa.resize(limbs, 0);
+        Self {
+            negative,
+            exponent: exponent - shift,
+            mantissa,
+            limbs,
+        }
+    }
+
+    fn add_signed(&self, other: &Self, other_negative: bool) -> Self {
+        let limbs = self.limbs.max(other.limbs);
+        if other.is_zero() {
+            let mut result = self.clone();
+            result.set_precision(limbs);
+            return result;
+        }
+        if self.is_zero() {
+            let mut result = other.clone();
+            result.negative = other_negative;
+            result.set_precision(limbs);
+            return result;
+        }
+        // both mantissas on the grid of the larger exponent, one spare bit for the carry and a guard limb
+        let exponent = self.exponent.max(other.exponent) + 1;
+        let width = limbs + 1;
+        let a = shift_right(&self.mantissa, exponent - self.exponent, width);
+        let b = shift_right(&other.mantissa, exponent - other.exponent, width);
+        let (negative, mantissa) = if self.negative == other_negative {
+            (self.negative, add_limbs(&a, &b))
+        } else {
+            match a.cmp(&b) {
+                Ordering::Less => (other_negative, sub_limbs(&b, &a)),
+                _ => (self.negative, sub_limbs(&a, &b)),
+            }
+        };
+        Self::normalized(negative, exponent, mantissa, limbs)
+    }
+}
+
+impl BigVec2 {
+    pub fn new(x: f64, y: f64, limbs: usize) -> Self {
+        Self {
+            x: BigFloat::from_f64(x, limbs),
+            y: BigFloat::from_f64(y, limbs),
+        }
+    }
+
+    pub fn to_f64(&self) -> glm::DVec2 {
+        glm::vec2(self.x.to_f64(), self.y.to_f64())
+    }
+
+    pub fn set_precision(&mut self, limbs: usize) {
+        self.x.set_precision(limbs);
+        self.y.set_precision(limbs);
+    }
+
+    // the point moved by a small f64 delta
+    pub fn offset(&self, delta: &glm::DVec2) -> Self {
+        Self {
+            x: &self.x + &BigFloat::from_f64(delta.x, self.x.limbs()),
+            y: &self.y + &BigFloat::from_f64(delta.y, self.y.limbs()),
+        }
+    }
+
+    // m * (x, y, 1) with the coefficients of m taken as exact
+    pub fn transform(&self, m: &glm::DMat3) -> Self {
+        let limbs = self.x.limbs();
+        let coefficient = |row: usize, col: usize| BigFloat::from_f64(m[(row, col)], limbs);
+        let row = |row: usize| {
+            &(&(&coefficient(row, 0) * &self.x) + &(&coefficient(row, 1) * &self.y)) + &coefficient(row, 2)
+        };
+        Self {
+            x: row(0),
+            y: row(1),
+        }
+    }
+}
+
+// enough limbs to tell apart the pixels of a view magnified by mag
+pub fn limbs_for(mag: f64) -> usize {
+    let bits = mag.max(1.0).log2() + 64.0;
+    (bits / LIMB_BITS as f64).ceil() as usize + 1
+}
+
+impl Add for &BigFloat {
+    type Output = BigFloat;
+
+    fn add(self, other: &BigFloat) -> BigFloat {
+        self.add_signed(other, other.negative)
+    }
+}
+
+impl Sub for &BigFloat {
+    type Output = BigFloat;
+
+    fn sub(self, other: &BigFloat) -> BigFloat {
+        self.add_signed(other, !other.negative)
+    }
+}
+
+impl Mul for &BigFloat {
+    type Output = BigFloat;
+
+    fn mul(self, other: &BigFloat) -> BigFloat {
+        let limbs = self.limbs.max(other.limbs);
+        if self.is_zero() || other.is_zero() {
+            return BigFloat::zero(limbs);
+        }
+        let (a, b) = (&self.mantissa, &other.mantissa);
+        // schoolbook multiplication, 0.a * 0.b = 0.product
+        let mut product = vec![0u32; a.len() + b.len()];
+        for i in (0..a.len()).rev() {
+            let mut carry = 0u64;
+            for j in (0..b.len()).rev() {
+                let t = a[i] as u64 * b[j] as u64 + product[i + j + 1] as u64 + carry;
+                product[i + j + 1] = t as u32;
+                carry = t >> 32;
+            }
+            product[i] = carry as u32;
+        }
+        BigFloat::normalized(self.negative != other.negative, self.exponent + other.exponent, product, limbs)
+    }
+}
+
+impl Neg for &BigFloat {
+    type Output = BigFloat;
+
+    fn neg(self) -> BigFloat {
+        let mut result = self.clone();
+        result.negative = !result.negative && !result.is_zero();
+        result
+    }
+}
+
+impl AddAssign<f64> for BigFloat {
+    fn add_assign(&mut self, other: f64) {
+        *self = &*self + &BigFloat::from_f64(other, self.limbs);
+    }
+}
+
+impl SubAssign<f64> for BigFloat {
+    fn sub_assign(&mut self, other: f64) {
+        *self = &*self - &BigFloat::from_f64(other, self.limbs);
+    }
+}
+
+// x * 2^n without overflowing the intermediate power of two
+fn ldexp(mut x: f64, mut n: i64) -> f64 {
+    while n > 1000 {
+        x *= 2f64.powi(1000);
+        n -= 1000;
+    }
+    while n < -1000 {
+        x *= 2f64.powi(-1000);
+        n += 1000;
+    }
+    x * 2f64.powi(n as i32)
+}
+
+// mantissa shifted towards the less significant limbs by `shift` bits, cut to `width` limbs
+fn shift_right(mantissa: &[u32], shift: i64, width: usize) -> Vec<u32> {
+    let mut result = vec![0u32; width];
+    let limb_shift = (shift / LIMB_BITS) as usize;
+    let bit_shift = (shift % LIMB_BITS) as u32;
+    for (i, limb) in result.iter_mut().enumerate().skip(limb_shift) {
+        let j = i - limb_shift;
+        let current = mantissa.get(j).copied().unwrap_or(0);
+        *limb = if bit_shift == 0 {
+            current
+        } else {
+            let previous = if j > 0 { mantissa.get(j - 1).copied().unwrap_or(0) } else { 0 };
+            (current >> bit_shift) | (previous << (32 - bit_shift))
+        };
+    }
+    result
+}
+
+fn shift_left(mantissa: &[u32], shift: i64) -> Vec<u32> {
+    let limb_shift = (shift / LIMB_BITS) as usize;
+    let bit_shift = (shift % LIMB_BITS) as u32;
+    (0..mantissa.len())
+        .map(|i| {
+            let current = mantissa.get(i + limb_shift).copied().unwrap_or(0);
+            if bit_shift == 0 {
+                current
+            } else {
+                let next = mantissa.get(i + limb_shift + 1).copied().unwrap_or(0);
+                (current << bit_shift) | (next >> (32 - bit_shift))
+            }
+        })
+        .collect()
+}
+
+fn add_limbs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u32; a.len()];
+    let mut carry = 0u64;
+    for i in (0..a.len()).rev() {
+        let t = a[i] as u64 + b[i] as u64 + carry;
+        result[i] = t as u32;
+        carry = t >> 32;
+    }
+    result
+}
+
+// a - b, where a >= b
+fn sub_limbs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u32; a.len()];
+    let mut borrow = 0i64;
+    for i in (0..a.len()).rev() {
+        let mut t = a[i] as i64 - b[i] as i64 - borrow;
+        borrow = 0;
+        if t < 0 {
+            t += 1 << 32;
+            borrow = 1;
+        }
+        result[i] = t as u32;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimal_round_trip() {
+        let zeros = "0".repeat(99);
+        for (text, decimal) in [
+            ("1.234567890123456789e-100", format!("0.{}1234567890123456789", zeros)),
+            ("-7.5e-101", format!("-0.{}075", zeros)),
+            (&format!("0.{}31415926535897932384626", zeros), format!("0.{}31415926535897932384626", zeros)),
+        ] {
+            let value = BigFloat::parse_decimal(text, 16).unwrap();
+            assert_eq!(value.to_decimal(130), decimal);
+            assert_eq!(BigFloat::parse_decimal(&decimal, 16).unwrap().to_decimal(130), decimal);
+        }
+        assert!(BigFloat::parse_decimal("1.5e-x", 4).is_err());
+        assert!(BigFloat::parse_decimal("-", 4).is_err());
+    }
+
+    #[test]
+    fn mixed_signs() {
+        let a = BigFloat::from_f64(3.0, 4);
+        let b = BigFloat::from_f64(-5.0, 4);
+        assert_eq!((&a + &b).to_f64(), -2.0);
+        assert_eq!((&b + &a).to_f64(), -2.0);
+        assert_eq!((&a - &b).to_f64(), 8.0);
+        assert_eq!((&b - &a).to_f64(), -8.0);
+        assert_eq!((&-&a - &b).to_f64(), 2.0);
+    }
+
+    #[test]
+    fn cancellation() {
+        let tiny = BigFloat::parse_decimal("1e-100", 16).unwrap();
+        assert!((&tiny - &tiny).is_zero());
+        assert!((&tiny + &-&tiny).is_zero());
+        // far below the precision of a double, the difference is still exact
+        let one = BigFloat::from_f64(1.0, 16);
+        let sum = &one + &tiny;
+        assert_eq!((&sum - &one).to_decimal(110), tiny.to_decimal(110));
+        assert_eq!((&one - &sum).to_decimal(110), (-&tiny).to_decimal(110));
+    }
+
+    #[test]
+    fn mul_matches_f64() {
+        for (a, b) in [(0.1, 0.3), (-1.5, 2.25), (1e-10, -3e-12), (-0.7436, -0.1318), (1.0 / 3.0, 3.0)] {
+            let product = (&BigFloat::from_f64(a, 4) * &BigFloat::from_f64(b, 4)).to_f64();
+            assert!((product - a * b).abs() <= (a * b).abs() * f64::EPSILON, "{} * {} = {}", a, b, product);
+        }
+        assert!((&BigFloat::from_f64(0.5, 4) * &BigFloat::zero(4)).is_zero());
+    }
+
+    #[test]
+    fn f64_round_trip() {
+        for value in [
+            1.0, -1.0, -3.75, 0.1, -1e-300, 1e300, f64::MAX, -f64::MAX,
+            f64::MIN_POSITIVE, -f64::MIN_POSITIVE, f64::MIN_POSITIVE * (1.0 + f64::EPSILON),
+            f64::MIN_POSITIVE / 3.0, 5e-324, -5e-324, 1.0 - f64::EPSILON,
+        ] {
+            assert_eq!(BigFloat::from_f64(value, 2).to_f64(), value);
+            assert_eq!(BigFloat::from_f64(value, 8).to_f64(), value);
+        }
+        for zero in [0.0, -0.0] {
+            let value = BigFloat::from_f64(zero, 2);
+            assert!(value.is_zero());
+            assert_eq!(value.to_f64(), 0.0);
+        }
+    }
+}
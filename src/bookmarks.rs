@@ -0,0 +1,334 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::bigfloat::{self, BigFloat, BigVec2};
+use crate::fractal::Fractal;
+
+// how often the file is checked for changes
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+// a saved view, a block of the locations file:
+//
+// [name]
+// center <x> <y>
+// mag <magnification>
+// iterations <count>|auto
+// fractal <name>
+// palette <name>|sine
+// julia <x> <y>
+//
+// everything but the center may be left out, points are in the complex plane
+#[derive(Clone, Debug)]
+pub struct Bookmark {
+    pub name: String,
+    pub center: BigVec2,
+    pub mag: f64,
+    // None for the automatic limit
+    pub iterations: Option<i32>,
+    pub fractal: Fractal,
+    // None for the sine colors
+    pub palette: Option<String>,
+    pub julia: Option<BigVec2>,
+}
+
+// the bookmarks of a locations file, read again when it changes on disk
+pub struct Bookmarks {
+    path: String,
+    modified: Option<SystemTime>,
+    checked: Instant,
+    pub list: Vec<Bookmark>,
+}
+
+impl Bookmark {
+    fn parse(name: &str, lines: &[(usize, &str)]) -> Result<Self, String> {
+        let mut center = None;
+        let mut mag = 1.0;
+        let mut iterations = None;
+        let mut fractal = Fractal::Mandelbrot;
+        let mut palette = None;
+        let mut julia = None;
+        // parsed after the magnification, its digits depend on it
+        let mut points = Vec::new();
+        for (number, line) in lines {
+            let error = |e: String| format!("line {}: {}", number, e);
+            let (key, value) = line.split_once(char::is_whitespace)
+                .map(|(key, value)| (key, value.trim()))
+                .ok_or_else(|| error(format!("expected a setting: {}", line)))?;
+            match key {
+                "center" | "julia" => points.push((*number, key, value)),
+                "mag" => mag = value.parse::<f64>().ok().filter(|mag| *mag > 0.0)
+                    .ok_or_else(|| error(format!("expected a positive magnification: {}", value)))?,
+                "iterations" => iterations = match value {
+                    "auto" => None,
+                    count => Some(count.parse::<i32>().map_err(|e| error(format!("{}: {}", count, e)))?),
+                },
+                "fractal" => fractal = value.parse::<Fractal>().map_err(error)?,
+                "palette" => palette = (value != "sine").then(|| value.to_owned()),
+                _ => return Err(error(format!("unknown setting: {}", key))),
+            }
+        }
+        let limbs = bigfloat::limbs_for(mag);
+        for (number, key, value) in points {
+            let point = parse_point(value, limbs).map_err(|e| format!("line {}: {}", number, e))?;
+            match key {
+                "center" => center = Some(point),
+                _ => julia = Some(point),
+            }
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            center: center.ok_or(format!("{} has no center", name))?,
+            mag,
+            iterations,
+            fractal,
+            palette,
+            julia,
+        })
+    }
+}
+
+// the digits that tell apart the pixels of the view, and a few more
+fn decimals(mag: f64) -> usize {
+    mag.max(1.0).log10().ceil() as usize + 8
+}
+
+fn parse_point(value: &str, limbs: usize) -> Result<BigVec2, String> {
+    let (x, y) = value.split_once(char::is_whitespace).ok_or(format!("expected <x> <y>: {}", value))?;
+    Ok(BigVec2 {
+        x: BigFloat::parse_decimal(x.trim(), limbs)?,
+        y: BigFloat::parse_decimal(y.trim(), limbs)?,
+    })
+}
+
+impl fmt::Display for Bookmark {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let decimals = decimals(self.mag);
+        let point = |point: &BigVec2| format!("{} {}", point.x.to_decimal(decimals), point.y.to_decimal(decimals));
+        writeln!(f, "[{}]", self.name)?;
+        writeln!(f, "center {}", point(&self.center))?;
+        writeln!(f, "mag {:e}", self.mag)?;
+        match self.iterations {
+            Some(count) => writeln!(f, "iterations {}", count)?,
+            None => writeln!(f, "iterations auto")?,
+        }
+        writeln!(f, "fractal {}", self.fractal)?;
+        writeln!(f, "palette {}", self.palette.as_deref().unwrap_or("sine"))?;
+        if let Some(julia) = &self.julia {
+            writeln!(f, "julia {}", point(julia))?;
+        }
+        Ok(())
+    }
+}
+
+impl Bookmarks {
+    // a file that doesn't exist yet has no bookmarks, it is created by the first one saved
+    pub fn load(path: &str) -> Result<Self, String> {
+        let mut bookmarks = Self {
+            path: path.to_owned(),
+            modified: None,
+            checked: Instant::now(),
+            list: Vec::new(),
+        };
+        bookmarks.read()?;
+        Ok(bookmarks)
+    }
+
+    fn read(&mut self) -> Result<(), String> {
+        self.modified = fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok();
+        let src = match fs::read_to_string(&self.path) {
+            Ok(src) => src,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("{}: {}", self.path, e)),
+        };
+        self.list = parse(&src).map_err(|e| format!("{}: {}", self.path, e))?;
+        Ok(())
+    }
+
+    // reads the file again when it was changed since, true if it was
+    // a file that doesn't parse keeps the bookmarks read before
+    pub fn reload(&mut self) -> Result<bool, String> {
+        if self.checked.elapsed() < CHECK_INTERVAL {
+            return Ok(false);
+        }
+        self.checked = Instant::now();
+        let modified = fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok();
+        if modified == self.modified {
+            return Ok(false);
+        }
+        self.read()?;
+        Ok(true)
+    }
+
+    // appended to the end of the file, what was written into it by hand stays as it was
+    // a blank line goes between the blocks, after finishing a last line that was left without a newline
+    pub fn add(&mut self, bookmark: Bookmark) -> Result<(), String> {
+        let existing = fs::read(&self.path).unwrap_or_default();
+        let separator = match existing.last() {
+            None => "",
+            Some(b'\n') => "\n",
+            Some(_) => "\n\n",
+        };
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)
+            .map_err(|e| format!("{}: {}", self.path, e))?;
+        // the block ends with a newline of its own
+        write!(file, "{}{}", separator, bookmark).map_err(|e| format!("{}: {}", self.path, e))?;
+        self.read()
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Bookmark> {
+        self.list.iter().find(|bookmark| bookmark.name.eq_ignore_ascii_case(name))
+    }
+
+    // the list on the terminal, with the keys that recall the first ones
+    pub fn print(&self, keys: &[String]) {
+        println!("{} bookmarks in {}", self.list.len(), self.path);
+        let width = keys.iter().map(String::len).max().unwrap_or(0);
+        for (idx, bookmark) in self.list.iter().enumerate() {
+            let key = keys.get(idx).map(String::as_str).unwrap_or("");
+            println!("  {:<width$} {} ({}, mag {:e})", key, bookmark.name, bookmark.fractal, bookmark.mag, width = width);
+        }
+    }
+}
+
+// blocks starting with [name], # starts a comment
+fn parse(src: &str) -> Result<Vec<Bookmark>, String> {
+    let mut bookmarks = Vec::new();
+    let mut current: Option<(&str, Vec<(usize, &str)>)> = None;
+    for (number, line) in src.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            Some(name) => {
+                if let Some((name, lines)) = current.take() {
+                    bookmarks.push(Bookmark::parse(name, &lines)?);
+                }
+                current = Some((name.trim(), Vec::new()));
+            },
+            None => match current.as_mut() {
+                Some((_, lines)) => lines.push((number + 1, line)),
+                None => return Err(format!("line {}: expected a [name] first: {}", number + 1, line)),
+            },
+        }
+    }
+    if let Some((name, lines)) = current {
+        bookmarks.push(Bookmark::parse(name, &lines)?);
+    }
+    Ok(bookmarks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deep() -> Bookmark {
+        let limbs = bigfloat::limbs_for(1e40);
+        Bookmark {
+            name: "Deep spiral".to_owned(),
+            center: BigVec2 {
+                x: BigFloat::parse_decimal("-0.74364388703715870475219150611477", limbs).unwrap(),
+                y: BigFloat::parse_decimal("0.13182590420531197049313205638514", limbs).unwrap(),
+            },
+            mag: 1e40,
+            iterations: Some(50000),
+            fractal: Fractal::Multibrot(2.5),
+            palette: Some("fire".to_owned()),
+            julia: Some(BigVec2::new(-0.8, 0.156, limbs)),
+        }
+    }
+
+    // a file of its own in the temporary directory, gone before and after the test
+    fn temp_file(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("bookmarks_test_{}_{}.txt", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn round_trip() {
+        let bookmark = deep();
+        let parsed = parse(&bookmark.to_string()).unwrap();
+        assert_eq!(parsed.len(), 1);
+        let parsed = &parsed[0];
+        assert_eq!(parsed.name, bookmark.name);
+        assert_eq!((parsed.mag, parsed.iterations, parsed.fractal), (bookmark.mag, bookmark.iterations, bookmark.fractal));
+        assert_eq!(parsed.palette, bookmark.palette);
+        // the digits of the magnification and a few more are kept
+        let decimals = decimals(bookmark.mag);
+        assert_eq!(parsed.center.x.to_decimal(decimals), bookmark.center.x.to_decimal(decimals));
+        assert_eq!(parsed.center.y.to_decimal(decimals), bookmark.center.y.to_decimal(decimals));
+        assert_eq!(parsed.julia.as_ref().map(BigVec2::to_f64), bookmark.julia.as_ref().map(BigVec2::to_f64));
+    }
+
+    #[test]
+    fn defaults_and_comments() {
+        let src = "# saved views\n\n[Whole set]\ncenter -0.5 0\n[Auto]\ncenter 0 1\niterations auto\npalette sine\n";
+        let parsed = parse(src).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!((parsed[0].mag, parsed[0].iterations, parsed[0].fractal), (1.0, None, Fractal::Mandelbrot));
+        assert_eq!((parsed[1].iterations, parsed[1].palette.as_deref()), (None, None));
+        assert!(parsed[1].julia.is_none());
+    }
+
+    #[test]
+    fn parse_errors() {
+        for src in [
+            "center 0 0",
+            "[No center]\nmag 10",
+            "[Bad mag]\ncenter 0 0\nmag -1",
+            "[Bad point]\ncenter 0",
+            "[Bad setting]\ncenter 0 0\nzoom 2",
+            "[Bad fractal]\ncenter 0 0\nfractal square",
+        ] {
+            assert!(parse(src).is_err(), "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn add_separates_the_blocks() {
+        let path = temp_file("add");
+        let mut bookmarks = Bookmarks::load(&path).unwrap();
+        let mut bookmark = deep();
+        bookmarks.add(bookmark.clone()).unwrap();
+        let first = fs::read_to_string(&path).unwrap();
+        assert!(first.starts_with('[') && first.ends_with('\n'));
+        bookmark.name = "Second".to_owned();
+        bookmarks.add(bookmark.clone()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\n{}", first, bookmark));
+        assert_eq!(bookmarks.list.len(), 2);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn add_after_a_hand_written_file() {
+        // no bookmarks yet, and the last line has no newline
+        let path = temp_file("hand");
+        fs::write(&path, "# my views").unwrap();
+        let mut bookmarks = Bookmarks::load(&path).unwrap();
+        assert!(bookmarks.list.is_empty());
+        let bookmark = deep();
+        bookmarks.add(bookmark.clone()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("# my views\n\n{}", bookmark));
+        assert_eq!(bookmarks.list.len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,308 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use gl::types::GLint;
+use std::fs::File;
+use std::io::BufWriter;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::fractal::Fractal;
+use crate::render::known_period;
+use crate::shader::Shader;
+use crate::texture::{Texture, TextureType};
+
+const BUDDHABROT_UNIT: GLint = 5;
+// samples a worker traces before it checks if it should stop
+const BATCH: u64 = 1024;
+// the window shows the new orbits this often
+const REFRESH: Duration = Duration::from_millis(250);
+// the longest orbit a worker keeps, as high as the automatic iteration count goes
+const MAX_LIMIT: i32 = 1 << 24;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DensityMode {
+    // the orbits of the points that escape
+    Buddhabrot,
+    // the orbits of the points that don't
+    AntiBuddhabrot,
+}
+
+// hit counts of the orbits on the pixels of the view, shared by the worker threads
+struct Density {
+    width: usize,
+    height: usize,
+    // red, green and blue count of every pixel, rows top to bottom
+    counts: Vec<AtomicU32>,
+    samples: AtomicU64,
+}
+
+// what the workers trace, it doesn't change while they run
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Job {
+    mode: DensityMode,
+    fractal: Fractal,
+    // iteration limit of the red, green and blue channel, different ones give the Nebulabrot
+    limits: [i32; 3],
+    mvp: glm::DMat3,
+}
+
+// the density of random orbits, traced on every core of the CPU in the background
+pub struct Buddhabrot {
+    width: usize,
+    height: usize,
+    job: Option<Job>,
+    density: Arc<Density>,
+    running: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+// shows the tone mapped density on the window
+pub struct BuddhabrotPass {
+    shader: Shader,
+    texture: Texture,
+    refreshed: Instant,
+}
+
+impl DensityMode {
+    // off, then both modes, then off again, for the key
+    pub fn next(mode: Option<Self>) -> Option<Self> {
+        match mode {
+            None => Some(DensityMode::Buddhabrot),
+            Some(DensityMode::Buddhabrot) => Some(DensityMode::AntiBuddhabrot),
+            Some(DensityMode::AntiBuddhabrot) => None,
+        }
+    }
+}
+
+impl FromStr for DensityMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "buddhabrot" => Ok(DensityMode::Buddhabrot),
+            "anti" | "anti-buddhabrot" => Ok(DensityMode::AntiBuddhabrot),
+            _ => Err(format!("Unknown density mode: {} (buddhabrot, anti)", s)),
+        }
+    }
+}
+
+// one limit for all channels, or one each for red, green and blue like 5000,500,50
+pub fn parse_limits(s: &str) -> Result<[i32; 3], String> {
+    let limits = s.split(',')
+        .map(|limit| limit.trim().parse::<i32>().map_err(|e| format!("Invalid iteration limit {}: {}", limit, e)))
+        .collect::<Result<Vec<_>, String>>()?;
+    if let Some(limit) = limits.iter().find(|limit| **limit > MAX_LIMIT) {
+        return Err(format!("Iteration limit {} is above the maximum of {}", limit, MAX_LIMIT));
+    }
+    match limits[..] {
+        [limit] if limit > 0 => Ok([limit; 3]),
+        [r, g, b] if r > 0 && g > 0 && b > 0 => Ok([r, g, b]),
+        _ => Err(format!("Expected one or three positive iteration limits: {}", s)),
+    }
+}
+
+impl Density {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            counts: (0..width * height * 3).map(|_| AtomicU32::new(0)).collect(),
+            samples: AtomicU64::new(0),
+        }
+    }
+
+    fn clear(&self) {
+        for count in self.counts.iter() {
+            count.store(0, Ordering::Relaxed);
+        }
+        self.samples.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Buddhabrot {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width: width as usize,
+            height: height as usize,
+            job: None,
+            density: Arc::new(Density::new(width as usize, height as usize)),
+            running: Arc::new(AtomicBool::new(false)),
+            workers: Vec::new(),
+        }
+    }
+
+    // keeps tracing when nothing changed, starts over otherwise
+    pub fn start(&mut self, mode: DensityMode, fractal: Fractal, limits: [i32; 3], mvp: &glm::DMat3) {
+        let job = Job {
+            mode,
+            fractal,
+            limits,
+            mvp: *mvp,
+        };
+        if self.job == Some(job) {
+            return;
+        }
+        self.stop();
+        self.density.clear();
+        self.job = Some(job);
+        self.running.store(true, Ordering::Relaxed);
+        let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or(0);
+        for idx in 0..threads as u64 {
+            let density = Arc::clone(&self.density);
+            let running = Arc::clone(&self.running);
+            // xorshift needs a state that is not 0
+            let seed = (seed ^ (idx + 1).wrapping_mul(0x9e3779b97f4a7c15)) | 1;
+            self.workers.push(std::thread::spawn(move || trace(job, &density, &running, seed)));
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        self.job = None;
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.density.samples.load(Ordering::Relaxed)
+    }
+
+    // RGB bytes, rows top to bottom, every channel scaled to its own brightest pixel
+    pub fn image(&self) -> Vec<u8> {
+        let counts: Vec<u32> = self.density.counts.iter().map(|count| count.load(Ordering::Relaxed)).collect();
+        let mut max = [0u32; 3];
+        for pixel in counts.chunks_exact(3) {
+            for channel in 0..3 {
+                max[channel] = max[channel].max(pixel[channel]);
+            }
+        }
+        // the square root brings out the faint orbits next to the bright ones
+        counts.chunks_exact(3)
+            .flat_map(|pixel| (0..3).map(move |channel| match max[channel] {
+                0 => 0,
+                max => ((pixel[channel] as f64 / max as f64).sqrt() * 255.0).round() as u8,
+            }))
+            .collect()
+    }
+
+    // traces until `samples` orbits are in, for the headless mode
+    pub fn write_png(&mut self, mode: DensityMode, fractal: Fractal, limits: [i32; 3], mvp: &glm::DMat3, samples: u64, filename: &str) -> Result<(), String> {
+        self.start(mode, fractal, limits, mvp);
+        while self.samples() < samples {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        self.stop();
+        let file = File::create(filename).map_err(|e| e.to_string())?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer.write_image_data(&self.image()).map_err(|e| e.to_string())
+    }
+}
+
+impl Drop for Buddhabrot {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// the body of a worker thread, random c values from the square around the sets until it is stopped
+fn trace(job: Job, density: &Density, running: &AtomicBool, mut seed: u64) {
+    let max_iter = job.limits.iter().copied().max().unwrap_or(0);
+    let inverse = glm::inverse(&glm::mat2(
+        job.mvp[(0, 0)], job.mvp[(0, 1)],
+        job.mvp[(1, 0)], job.mvp[(1, 1)],
+    ));
+    let center = glm::vec2(job.mvp[(0, 2)], job.mvp[(1, 2)]);
+    let mut orbit: Vec<glm::DVec2> = Vec::with_capacity(max_iter as usize);
+    while running.load(Ordering::Relaxed) {
+        for _ in 0..BATCH {
+            let c = glm::vec2(random(&mut seed) * 4.0 - 2.0, random(&mut seed) * 4.0 - 2.0);
+            // the cardioid and the bulb never escape, no need to follow them
+            if job.mode == DensityMode::Buddhabrot && job.fractal == Fractal::Mandelbrot && known_period(c) > 0 {
+                continue;
+            }
+            orbit.clear();
+            let mut z = glm::vec2(0.0, 0.0);
+            while orbit.len() < max_iter as usize && glm::dot(&z, &z) <= 4.0 {
+                z = job.fractal.step(z, c);
+                orbit.push(z);
+            }
+            let escaped = glm::dot(&z, &z) > 4.0;
+            for (channel, limit) in job.limits.iter().enumerate() {
+                let points = match job.mode {
+                    DensityMode::Buddhabrot if escaped && orbit.len() <= *limit as usize => &orbit[..],
+                    DensityMode::AntiBuddhabrot if !escaped || orbit.len() > *limit as usize => &orbit[..*limit as usize],
+                    _ => continue,
+                };
+                // z_1 is c itself, it would only add an even glow over the whole square
+                for point in points.iter().skip(1) {
+                    let tex = inverse * (point - center);
+                    let col = ((tex.x + 1.0) * 0.5 * density.width as f64).floor();
+                    let row = ((1.0 - tex.y) * 0.5 * density.height as f64).floor();
+                    if col >= 0.0 && row >= 0.0 && col < density.width as f64 && row < density.height as f64 {
+                        let idx = (row as usize * density.width + col as usize) * 3 + channel;
+                        density.counts[idx].fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+        density.samples.fetch_add(BATCH, Ordering::Relaxed);
+    }
+}
+
+// xorshift64*, uniform in [0, 1)
+fn random(state: &mut u64) -> f64 {
+    *state ^= *state >> 12;
+    *state ^= *state << 25;
+    *state ^= *state >> 27;
+    (state.wrapping_mul(0x2545f4914f6cdd1d) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl BuddhabrotPass {
+    pub fn new(vertex: &str, fragment: &str, width: i32, height: i32) -> Result<Self, String> {
+        let mut shader = Shader::new(vertex, fragment)?;
+        let texture = Texture::from_data(&vec![0; (width * height * 3) as usize], width as u32, height as u32, BUDDHABROT_UNIT, TextureType::RGB);
+        texture.link(&mut shader, "density");
+        Ok(Self {
+            shader,
+            texture,
+            refreshed: Instant::now(),
+        })
+    }
+
+    // uploads the density every REFRESH, so the picture fills in while the workers run
+    pub fn draw<F: FnMut()>(&mut self, buddhabrot: &Buddhabrot, mut draw: F) {
+        if self.refreshed.elapsed() >= REFRESH {
+            self.texture.set_data(&buddhabrot.image(), buddhabrot.width as u32, buddhabrot.height as u32, TextureType::RGB);
+            self.refreshed = Instant::now();
+        }
+        self.texture.bind();
+        self.shader.bind();
+        draw();
+        self.shader.unbind();
+    }
+}
@@ -17,6 +17,8 @@ along with this program.  If not, see <https://www.gnu.org/licenses/>.
 use gl::types::{GLuint, GLint, GLfloat, GLsizeiptr, GLsizei, GLenum};
 use std::{mem};
 
+use crate::texture::Texture;
+
 
 pub struct VertexArray {
     id: GLuint,
@@ -36,6 +38,18 @@ pub struct ElementBuffer {
     bound: bool,
 }
 
+// offscreen render target, drawing goes into a texture instead of the window
+pub struct FrameBuffer {
+    id: GLuint,
+    // stencil for the deep zoom passes, they need it the same way on the window
+    stencil: GLuint,
+    attachments: Vec<GLenum>,
+    width: i32,
+    height: i32,
+
+    bound: bool,
+}
+
 pub struct VertexAttribDescriptor {
     items: std::collections::BTreeMap<u8, LayoutItem>
 }
@@ -251,3 +265,146 @@ impl Drop for ElementBuffer {
         }
     }
 }
+
+impl FrameBuffer {
+    // `texture` becomes the color attachment, it has to be width x height
+    pub fn new(texture: &Texture, width: i32, height: i32) -> Result<Self, String> {
+        Self::with_attachments(&[texture], width, height)
+    }
+
+    // the textures are the color attachments in order, the outputs at location 0, 1, ... of the shaders
+    pub fn with_attachments(textures: &[&Texture], width: i32, height: i32) -> Result<Self, String> {
+        let mut tmp_id: GLuint = 0;
+        let mut stencil: GLuint = 0;
+        let attachments: Vec<GLenum> = (0..textures.len() as GLenum).map(|idx| gl::COLOR_ATTACHMENT0 + idx).collect();
+        unsafe {
+            gl::GenFramebuffers(1, &mut tmp_id);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, tmp_id);
+            for (texture, attachment) in textures.iter().zip(attachments.iter()) {
+                gl::FramebufferTexture2D(gl::FRAMEBUFFER, *attachment, gl::TEXTURE_2D, texture.id(), 0);
+            }
+            gl::DrawBuffers(attachments.len() as GLsizei, attachments.as_ptr());
+
+            gl::GenRenderbuffers(1, &mut stencil);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, stencil);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width as GLsizei, height as GLsizei);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, stencil);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+        }
+        let mut instance = Self {
+            id: tmp_id,
+            stencil,
+            attachments,
+            width,
+            height,
+            bound: true,
+        };
+        let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        instance.unbind();
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(format!("Incomplete framebuffer: 0x{:x}", status));
+        }
+        Ok(instance)
+    }
+
+    pub fn bind(&mut self) {
+        if !self.bound {
+            unsafe {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+            }
+            self.bound = true;
+        }
+    }
+
+    pub fn unbind(&mut self) {
+        if self.bound {
+            unsafe {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            }
+            self.bound = false;
+        }
+    }
+
+    // copies the first color attachment onto the window
+    pub fn blit(&mut self) {
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id);
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+            gl::BlitFramebuffer(0, 0, self.width, self.height, 0, 0, self.width, self.height, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        self.bound = false;
+    }
+
+    // moves the content of a color attachment by (dx, dy) pixels, what moves in from outside is left as it was
+    // the copy goes through `scratch`, a copy within one texture must not overlap
+    pub fn shift(&mut self, attachment: usize, scratch: &mut FrameBuffer, dx: i32, dy: i32) {
+        let (width, height) = (self.width, self.height);
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id);
+            gl::ReadBuffer(self.attachments[attachment]);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, scratch.id);
+            gl::BlitFramebuffer(0, 0, width, height, dx, dy, width + dx, height + dy, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+            // the read buffer belongs to this framebuffer, it goes back to the picture before it is unbound
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, scratch.id);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.id);
+            gl::DrawBuffers(1, &self.attachments[attachment]);
+            // only the part that came from this texture goes back
+            let (x0, y0) = (dx.max(0), dy.max(0));
+            let (x1, y1) = ((width + dx).min(width), (height + dy).min(height));
+            gl::BlitFramebuffer(x0, y0, x1, y1, x0, y0, x1, y1, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+            gl::DrawBuffers(self.attachments.len() as GLsizei, self.attachments.as_ptr());
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        scratch.bound = false;
+        self.bound = false;
+    }
+
+    // fills a rectangle of a color attachment with `value`
+    pub fn clear(&mut self, attachment: usize, x: i32, y: i32, width: i32, height: i32, value: [f32; 4]) {
+        self.bind();
+        unsafe {
+            gl::Enable(gl::SCISSOR_TEST);
+            gl::Scissor(x, y, width, height);
+            gl::ClearBufferfv(gl::COLOR, attachment as GLint, value.as_ptr());
+            gl::Disable(gl::SCISSOR_TEST);
+        }
+    }
+
+    // red channel of a float color attachment, rows bottom to top
+    pub fn read_red(&mut self) -> Vec<f32> {
+        let mut data = vec![0f32; (self.width * self.height) as usize];
+        self.bind();
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 4);
+            gl::ReadPixels(0, 0, self.width as GLsizei, self.height as GLsizei, gl::RED, gl::FLOAT, data.as_mut_ptr() as *mut _);
+        }
+        data
+    }
+
+    // all four channels of a float color attachment, rows bottom to top
+    pub fn read_rgba(&mut self, attachment: usize) -> Vec<f32> {
+        let mut data = vec![0f32; (self.width * self.height * 4) as usize];
+        self.bind();
+        unsafe {
+            gl::ReadBuffer(self.attachments[attachment]);
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 4);
+            gl::ReadPixels(0, 0, self.width as GLsizei, self.height as GLsizei, gl::RGBA, gl::FLOAT, data.as_mut_ptr() as *mut _);
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+        }
+        data
+    }
+}
+
+impl Drop for FrameBuffer {
+    fn drop(&mut self) {
+        self.unbind();
+        unsafe {
+            gl::DeleteRenderbuffers(1, &self.stencil);
+            gl::DeleteFramebuffers(1, &self.id);
+        }
+    }
+}
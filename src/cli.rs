@@ -0,0 +1,436 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::bigfloat::{self, BigFloat, BigVec2};
+use crate::buddhabrot::{self, DensityMode};
+use crate::coloring::{ColorMode, Coloring, Interior};
+use crate::fractal::Fractal;
+use crate::iterations::IterationLimit;
+use crate::newton::Polynomial;
+use crate::sampling::{self, SamplePattern, Supersampling};
+use crate::trap::TrapShape;
+
+const DEFAULT_SIZE: (u32, u32) = (1920, 1080);
+const MAX_SIZE: u32 = 16384;
+pub const SHADER_DIR: &str = "resources/shader";
+// next to the shader directory
+const PALETTE_DIR: &str = "palettes";
+// the saved views, in the working directory unless --bookmarks says otherwise
+pub const BOOKMARKS_FILE: &str = "bookmarks.txt";
+// the key bindings, the default keys without it
+pub const KEYS_FILE: &str = "keys.txt";
+
+pub const USAGE: &str = "\
+Usage: mandelbrot [view] [options]       explore in a window
+       mandelbrot render <output.png> [options]
+                                         render a picture on the CPU without a window
+       mandelbrot help                   show this
+
+View:
+  --center <x>,<y>          middle of the view in the complex plane, any number of digits
+                            (the whole set, -0.5*width/height,0)
+  --zoom <mag>              magnification, 1 shows the whole set (1)
+  --iterations <n>|auto     iteration limit, auto follows the zoom (2 in the window, 256 rendered)
+  --julia <x>,<y>           the Julia set of this point instead of the Mandelbrot set
+  --size <width>x<height>   window or picture size in pixels (1920x1080)
+  --bookmark <name>         start at a saved view
+  --bookmarks <file>        the file of the saved views (bookmarks.txt)
+
+Fractal:
+  --fractal <name>          mandelbrot, multibrot[:d], burning-ship, tricorn, celtic, newton, nova
+  --roots <x>,<y>;...       roots of the Newton and Nova polynomial
+  --relaxation <a>          step size of the Newton method, positive (1)
+
+Colors:
+  --coloring <mode>         banded, smooth, histogram, distance, trap (banded)
+  --interior <mode>         flat, period (flat)
+  --palette <name>|<file>   a palette of resources/palettes, next to the shaders, or a file
+  --escape-radius <r>       at least 2 (2 for banded, 256 otherwise)
+  --trap <shape>            point, line, cross, circle, image (point)
+  --trap-image <file.png>   the picture of the image trap
+  --supersample <n>         n x n samples per pixel, 1 to 4 (1)
+  --sample-pattern <name>   grid, rotated, jittered (rotated)
+
+Buddhabrot:
+  --buddhabrot <mode>       buddhabrot, anti: the orbit density instead of the escape time
+  --limits <n>|<r>,<g>,<b>  iteration limits of the color channels (5000,500,50)
+  --samples <count>         orbits traced for a rendered picture (10000000)
+
+Window only:
+  --fullscreen              fullscreen at the size of the desktop
+  --inertia on|off          the view glides on after the keys are let go (on)
+  --keys <file>             the key bindings (keys.txt), h shows them in the window
+  --shader-dir <dir>        where the shaders are loaded from (resources/shader)
+";
+
+pub enum Command {
+    View,
+    // the file to write
+    Render(String),
+    Help,
+}
+
+// everything the command line sets, the defaults where it doesn't
+pub struct Options {
+    pub size: (u32, u32),
+    pub fullscreen: bool,
+    pub shader_dir: String,
+    // the middle of the view in the complex plane, None for the view the program always started with
+    pub center: Option<BigVec2>,
+    pub zoom: f64,
+    // None leaves it to the command
+    pub iterations: Option<IterationLimit>,
+    pub julia: Option<BigVec2>,
+    pub fractal: Fractal,
+    pub polynomial: Polynomial,
+    pub coloring: Coloring,
+    pub trap_image: Option<String>,
+    pub supersampling: Supersampling,
+    // a palette name or file
+    pub palette: Option<String>,
+    pub bookmarks: String,
+    pub bookmark: Option<String>,
+    pub inertia: bool,
+    pub keys: String,
+    pub density: Option<DensityMode>,
+    pub limits: [i32; 3],
+    pub samples: u64,
+}
+
+// removes `name <value>` from the arguments, wherever it is
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    match args.iter().position(|arg| arg == name) {
+        Some(idx) => {
+            let value = args.get(idx + 1).ok_or(format!("{} needs a value", name))?.clone();
+            args.drain(idx..idx + 2);
+            Ok(Some(value))
+        },
+        None => Ok(None),
+    }
+}
+
+// removes `name` from the arguments, true if it was there
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|arg| arg == name) {
+        Some(idx) => {
+            args.remove(idx);
+            true
+        },
+        None => false,
+    }
+}
+
+// the value of an option parsed, with the option named in the error
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value.parse::<T>().map_err(|e| format!("{} {}: {}", name, value, e))
+}
+
+// `x,y` in as many digits as given
+fn parse_point(name: &str, value: &str, limbs: usize) -> Result<BigVec2, String> {
+    let (x, y) = value.split_once(',').ok_or(format!("{} expects <x>,<y>: {}", name, value))?;
+    let coordinate = |text: &str| BigFloat::parse_decimal(text.trim(), limbs).map_err(|e| format!("{} {}: {}", name, value, e));
+    Ok(BigVec2 {
+        x: coordinate(x)?,
+        y: coordinate(y)?,
+    })
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let error = || format!("--size expects <width>x<height> between 16 and {}: {}", MAX_SIZE, value);
+    let (width, height) = value.split_once(['x', 'X']).ok_or_else(error)?;
+    let side = |side: &str| side.trim().parse::<u32>().ok().filter(|side| (16..=MAX_SIZE).contains(side)).ok_or_else(error);
+    Ok((side(width)?, side(height)?))
+}
+
+// the command and its options, the options may come anywhere after the command
+pub fn parse(mut args: Vec<String>) -> Result<(Command, Options), String> {
+    let command = match args.first().map(String::as_str) {
+        Some("view") => {
+            args.remove(0);
+            Command::View
+        },
+        Some("render") => {
+            args.remove(0);
+            match args.first() {
+                Some(output) if !output.starts_with("--") => Command::Render(args.remove(0)),
+                _ => return Err("render needs the file to write: mandelbrot render <output.png> [options]".to_owned()),
+            }
+        },
+        Some("help") | Some("--help") | Some("-h") => Command::Help,
+        Some(arg) if !arg.starts_with("--") => return Err(format!("Unknown command: {} (view, render, help)", arg)),
+        _ => Command::View,
+    };
+    if let Command::Help = command {
+        return Ok((command, Options::default()));
+    }
+
+    let mut options = Options::default();
+    if let Some(size) = take_option(&mut args, "--size")? {
+        options.size = parse_size(&size)?;
+    }
+    options.fullscreen = take_flag(&mut args, "--fullscreen");
+    if let Some(dir) = take_option(&mut args, "--shader-dir")? {
+        if !std::path::Path::new(&dir).is_dir() {
+            return Err(format!("--shader-dir {}: not a directory", dir));
+        }
+        options.shader_dir = dir;
+    }
+    if let Some(zoom) = take_option(&mut args, "--zoom")? {
+        options.zoom = parse_value::<f64>("--zoom", &zoom)?;
+        if !(options.zoom.is_finite() && options.zoom > 0.0) {
+            return Err(format!("--zoom has to be positive: {}", zoom));
+        }
+    }
+    // the digits of the points depend on the zoom
+    let limbs = bigfloat::limbs_for(options.zoom);
+    if let Some(center) = take_option(&mut args, "--center")? {
+        options.center = Some(parse_point("--center", &center, limbs)?);
+    }
+    if let Some(julia) = take_option(&mut args, "--julia")? {
+        options.julia = Some(parse_point("--julia", &julia, limbs)?);
+    }
+    options.iterations = match take_option(&mut args, "--iterations")?.as_deref() {
+        Some("auto") => Some(IterationLimit::new(true, 2)),
+        Some(iter) => match parse_value::<i32>("--iterations", iter)? {
+            count if count > 0 => Some(IterationLimit::new(false, count)),
+            _ => return Err(format!("--iterations has to be positive or auto: {}", iter)),
+        },
+        None => None,
+    };
+
+    if let Some(name) = take_option(&mut args, "--fractal")? {
+        options.fractal = name.parse::<Fractal>()?;
+    }
+    if let Some(roots) = take_option(&mut args, "--roots")? {
+        options.polynomial = roots.parse::<Polynomial>()?;
+    }
+    if let Some(relaxation) = take_option(&mut args, "--relaxation")? {
+        options.polynomial.relaxation = parse_value::<f64>("--relaxation", &relaxation)?;
+        if !(options.polynomial.relaxation.is_finite() && options.polynomial.relaxation > 0.0) {
+            return Err(format!("--relaxation has to be positive: {}", relaxation));
+        }
+    }
+
+    options.coloring = match take_option(&mut args, "--coloring")?.as_deref() {
+        Some("banded") | None => Coloring::new(ColorMode::Banded),
+        Some("smooth") => Coloring::new(ColorMode::Smooth),
+        Some("histogram") => Coloring::new(ColorMode::Histogram),
+        Some("distance") => Coloring::new(ColorMode::Distance),
+        Some("trap") => Coloring::new(ColorMode::Trap),
+        Some(other) => return Err(format!("Unknown coloring: {} (banded, smooth, histogram, distance, trap)", other)),
+    };
+    options.coloring.interior = match take_option(&mut args, "--interior")?.as_deref() {
+        Some("flat") | None => Interior::Flat,
+        Some("period") => Interior::Period,
+        Some(other) => return Err(format!("Unknown interior coloring: {} (flat, period)", other)),
+    };
+    options.trap_image = take_option(&mut args, "--trap-image")?;
+    options.coloring.trap.shape = match take_option(&mut args, "--trap")? {
+        Some(shape) => shape.parse::<TrapShape>()?,
+        None if options.trap_image.is_some() => TrapShape::Image,
+        None => TrapShape::Point,
+    };
+    if options.coloring.trap.shape == TrapShape::Image && options.trap_image.is_none() {
+        return Err("The image trap needs --trap-image <file.png>".to_owned());
+    }
+    if let Some(radius) = take_option(&mut args, "--escape-radius")? {
+        options.coloring.escape_radius = parse_value::<f64>("--escape-radius", &radius)?;
+        if !(options.coloring.escape_radius.is_finite() && options.coloring.escape_radius >= 2.0) {
+            return Err("The escape radius has to be at least 2".to_owned());
+        }
+    }
+    // NxN samples per pixel against aliasing
+    if let Some(pattern) = take_option(&mut args, "--sample-pattern")? {
+        options.supersampling.pattern = pattern.parse::<SamplePattern>()?;
+    }
+    if let Some(size) = take_option(&mut args, "--supersample")? {
+        options.supersampling.size = match parse_value::<i32>("--supersample", &size)? {
+            size if (1..=sampling::MAX_SIZE).contains(&size) => size,
+            _ => return Err(format!("--supersample has to be between 1 and {}: {}", sampling::MAX_SIZE, size)),
+        };
+    }
+    options.palette = take_option(&mut args, "--palette")?;
+
+    if let Some(file) = take_option(&mut args, "--bookmarks")? {
+        options.bookmarks = file;
+    }
+    options.bookmark = take_option(&mut args, "--bookmark")?;
+    options.inertia = match take_option(&mut args, "--inertia")?.as_deref() {
+        Some("on") | None => true,
+        Some("off") => false,
+        Some(other) => return Err(format!("Unknown inertia: {} (on, off)", other)),
+    };
+    if let Some(file) = take_option(&mut args, "--keys")? {
+        options.keys = file;
+    }
+
+    // the orbit density instead of the escape time, on the CPU
+    if let Some(mode) = take_option(&mut args, "--buddhabrot")? {
+        options.density = Some(mode.parse::<DensityMode>()?);
+    }
+    if let Some(limits) = take_option(&mut args, "--limits")? {
+        options.limits = buddhabrot::parse_limits(&limits)?;
+    }
+    if let Some(samples) = take_option(&mut args, "--samples")? {
+        options.samples = match parse_value::<u64>("--samples", &samples)? {
+            0 => return Err("--samples has to be positive".to_owned()),
+            samples => samples,
+        };
+    }
+
+    if let Some(arg) = args.first() {
+        return Err(match arg.starts_with("--") {
+            true => format!("Unknown option: {}, see mandelbrot help", arg),
+            false => format!("Unexpected argument: {}, see mandelbrot help", arg),
+        });
+    }
+    if let Command::Render(_) = command {
+        if options.fullscreen {
+            return Err("--fullscreen is for the window, render writes a picture of --size".to_owned());
+        }
+    }
+    Ok((command, options))
+}
+
+impl Options {
+    // the palettes are found in the same resource directory as the shaders
+    pub fn palette_dir(&self) -> String {
+        let root = std::path::Path::new(&self.shader_dir).parent().unwrap_or(std::path::Path::new(""));
+        root.join(PALETTE_DIR).to_string_lossy().into_owned()
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            size: DEFAULT_SIZE,
+            fullscreen: false,
+            shader_dir: SHADER_DIR.to_owned(),
+            center: None,
+            zoom: 1.0,
+            iterations: None,
+            julia: None,
+            fractal: Fractal::Mandelbrot,
+            polynomial: Polynomial::new(),
+            coloring: Coloring::new(ColorMode::Banded),
+            trap_image: None,
+            supersampling: Supersampling::new(SamplePattern::RotatedGrid, 1),
+            palette: None,
+            bookmarks: BOOKMARKS_FILE.to_owned(),
+            bookmark: None,
+            inertia: true,
+            keys: KEYS_FILE.to_owned(),
+            density: None,
+            limits: [5000, 500, 50],
+            samples: 10_000_000,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_owned).collect()
+    }
+
+    fn parsed(line: &str) -> Options {
+        parse(args(line)).unwrap().1
+    }
+
+    #[test]
+    fn defaults() {
+        let (command, options) = parse(Vec::new()).unwrap();
+        assert!(matches!(command, Command::View));
+        assert_eq!(options.size, DEFAULT_SIZE);
+        assert!(options.center.is_none() && options.julia.is_none() && options.iterations.is_none());
+        assert_eq!((options.zoom, options.fractal, options.coloring.mode), (1.0, Fractal::Mandelbrot, ColorMode::Banded));
+        assert_eq!((options.shader_dir.as_str(), options.palette_dir()), (SHADER_DIR, "resources/palettes".to_owned()));
+        assert_eq!((options.bookmarks.as_str(), options.keys.as_str()), (BOOKMARKS_FILE, KEYS_FILE));
+        assert_eq!((options.inertia, options.limits), (true, [5000, 500, 50]));
+    }
+
+    #[test]
+    fn commands() {
+        assert!(matches!(parse(args("view --zoom 2")).unwrap().0, Command::View));
+        assert!(matches!(parse(args("help")).unwrap().0, Command::Help));
+        match parse(args("render out.png --size 64x48")).unwrap() {
+            (Command::Render(output), options) => assert_eq!((output.as_str(), options.size), ("out.png", (64, 48))),
+            _ => panic!("expected render"),
+        }
+        assert!(parse(args("render")).is_err());
+        assert!(parse(args("render --size 64x48")).is_err());
+        assert!(parse(args("render out.png --fullscreen")).is_err());
+    }
+
+    #[test]
+    fn options_in_any_order() {
+        let options = parsed("--iterations auto --center -0.743643887037158704752,0.131825904205311970493 --zoom 1e20 --julia 0.25,-0.5");
+        assert!(options.iterations.is_some_and(|limit| limit.auto));
+        assert_eq!(options.zoom, 1e20);
+        // every digit of the center is kept at the depth of the zoom
+        assert_eq!(options.center.unwrap().x.to_decimal(21), "-0.743643887037158704752");
+        assert_eq!(options.julia.unwrap().to_f64(), glm::vec2(0.25, -0.5));
+        let options = parsed("--coloring distance --escape-radius 1000 --supersample 3 --sample-pattern grid --inertia off");
+        assert_eq!((options.coloring.mode, options.coloring.escape_radius), (ColorMode::Distance, 1000.0));
+        assert_eq!(options.supersampling, Supersampling::new(SamplePattern::Grid, 3));
+        assert!(!options.inertia);
+        assert_eq!(parsed("--limits 1000").limits, [1000; 3]);
+    }
+
+    #[test]
+    fn errors() {
+        for line in [
+            "--zoom",
+            "--zoom 0",
+            "--zoom -2",
+            "--zoom nan",
+            "--zoom inf",
+            "--center 1",
+            "--center a,b",
+            "--julia 0.5",
+            "--iterations 0",
+            "--iterations lots",
+            "--size 1920",
+            "--size 8x8",
+            "--size 100000x100",
+            "--escape-radius 1.5",
+            "--escape-radius nan",
+            "--escape-radius inf",
+            "--relaxation 0",
+            "--relaxation -1",
+            "--relaxation nan",
+            "--coloring plaid",
+            "--interior hollow",
+            "--trap image",
+            "--supersample 5",
+            "--inertia maybe",
+            "--limits 0",
+            "--limits 100,200",
+            "--samples 0",
+            "--shader-dir /nonexistent/shader",
+            "--fractal square",
+            "--unknown",
+            "stray",
+        ] {
+            assert!(parse(args(line)).is_err(), "{}", line);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::palette::Palette;
+use crate::shader::Shader;
+use crate::trap::{Trap, TrapShape};
+use crate::uniform::Uniform;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorMode {
+    // sine of the integer iteration count
+    Banded,
+    // normalized iteration count, continuous across the bands
+    Smooth,
+    // normalized iteration count spread evenly over the colors by its distribution on the screen
+    Histogram,
+    // smooth colors darkened close to the boundary, by a distance estimated from the derivative
+    Distance,
+    // by how close the orbit got to the trap
+    Trap,
+}
+
+// color of the points that never escape
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Interior {
+    // the same color as the last iteration count
+    Flat,
+    // by the length of the cycle their orbit falls into
+    Period,
+}
+
+// how the escape time of a pixel turns into its color, shared by the shaders and the CPU renderer
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Coloring {
+    pub mode: ColorMode,
+    // |z| above which a point counts as escaped
+    pub escape_radius: f64,
+    pub interior: Interior,
+    pub trap: Trap,
+}
+
+// what the escape-time loop found out about a pixel
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Escape {
+    pub iters: i32,
+    // |z|^2 right after the escape
+    pub z_2: f64,
+    // the running derivative |dz|
+    pub dr: f64,
+    // length of the cycle of a point that never escapes, 0 if none was found
+    pub period: i32,
+    // closest the orbit got to the trap
+    pub trap_distance: f64,
+    // the first pixel of the image trap the orbit went through
+    pub trap_hit: Option<[u8; 3]>,
+}
+
+impl ColorMode {
+    // has to match the COLORING_* defines of the fragment shaders
+    pub fn id(&self) -> i32 {
+        match self {
+            ColorMode::Banded => 0,
+            ColorMode::Smooth => 1,
+            ColorMode::Histogram => 2,
+            ColorMode::Distance => 3,
+            ColorMode::Trap => 4,
+        }
+    }
+
+    // smooth coloring only looks right when |z| got far past 2 by the time it escaped
+    pub fn default_radius(&self) -> f64 {
+        match self {
+            ColorMode::Banded => 2.0,
+            ColorMode::Smooth | ColorMode::Histogram | ColorMode::Distance | ColorMode::Trap => 256.0,
+        }
+    }
+}
+
+impl Interior {
+    // has to match the INTERIOR_* defines of the fragment shaders
+    pub fn id(&self) -> i32 {
+        match self {
+            Interior::Flat => 0,
+            Interior::Period => 1,
+        }
+    }
+
+    pub fn toggle(&self) -> Self {
+        match self {
+            Interior::Flat => Interior::Period,
+            Interior::Period => Interior::Flat,
+        }
+    }
+}
+
+impl Coloring {
+    pub fn new(mode: ColorMode) -> Self {
+        Self {
+            mode,
+            escape_radius: mode.default_radius(),
+            interior: Interior::Flat,
+            trap: Trap::new(TrapShape::Point),
+        }
+    }
+
+    // cycles through the modes, the radius goes back to the default of the new one
+    pub fn toggle(&self) -> Self {
+        let next = match self.mode {
+            ColorMode::Banded => ColorMode::Smooth,
+            ColorMode::Smooth => ColorMode::Histogram,
+            ColorMode::Histogram => ColorMode::Distance,
+            ColorMode::Distance => ColorMode::Trap,
+            ColorMode::Trap => ColorMode::Banded,
+        };
+        Self {
+            interior: self.interior,
+            trap: self.trap,
+            ..Self::new(next)
+        }
+    }
+
+    pub fn set_uniforms(&self, shader: &mut Shader) {
+        Uniform::new("coloring", shader).seti(self.mode.id(), shader);
+        Uniform::new("escape_radius", shader).setf(self.escape_radius as f32, shader);
+        Uniform::new("interior", shader).seti(self.interior.id(), shader);
+        self.trap.set_uniforms(shader);
+    }
+
+    // the iteration count the colors come from, as in pixel_color of the fragment shaders
+    // z_2 is |z|^2 right after the escape, power the degree of the formula
+    pub fn count(&self, iters: i32, z_2: f64, max_iter: i32, power: f64) -> f64 {
+        let mut n = iters as f64;
+        if self.mode != ColorMode::Banded && iters < max_iter {
+            n += 1.0 - (0.5 * z_2.ln()).ln() / power.max(1.0001).ln();
+        }
+        n
+    }
+
+    // iteration count to color, the histogram mode is colored by histogram.rs instead
+    // pixel is the size of a pixel in the complex plane, only the distance mode needs it
+    pub fn color(&self, escape: &Escape, pixel: f64, max_iter: i32, power: f64, palette: Option<&Palette>) -> [u8; 3] {
+        if self.mode == ColorMode::Trap {
+            match (self.trap.shape, escape.trap_hit) {
+                // the orbits that missed the image get the smooth colors
+                (TrapShape::Image, Some(hit)) => return hit,
+                (TrapShape::Image, None) => {},
+                _ => return base_color(4.0 * (self.trap.size / escape.trap_distance.max(1e-30)).log2(), palette),
+            }
+        }
+        if escape.iters >= max_iter && self.interior == Interior::Period {
+            return period_color(escape.period, palette);
+        }
+        let n = self.count(escape.iters, escape.z_2, max_iter, power);
+        let base = base_color(n, palette);
+        if self.mode != ColorMode::Distance {
+            return base;
+        }
+        if escape.iters >= max_iter {
+            return [0, 0, 0];
+        }
+        // 0.5 |z| ln|z| / |dz| in pixels
+        let z_2 = escape.z_2;
+        let distance = 0.25 * z_2.sqrt() * z_2.ln() / (escape.dr * pixel);
+        let shade = distance.clamp(0.0, 1.0).sqrt();
+        base.map(|channel| to_byte(channel as f64 / 255.0 * shade))
+    }
+}
+
+fn base_color(n: f64, palette: Option<&Palette>) -> [u8; 3] {
+    match palette {
+        Some(palette) => palette.color(n),
+        None => sine_color(n),
+    }
+}
+
+// periods spread by the golden ratio as in period_color of the shaders, black if unknown
+fn period_color(period: i32, palette: Option<&Palette>) -> [u8; 3] {
+    if period == 0 {
+        return [0, 0, 0];
+    }
+    let t = (0.618034 * period as f64).fract();
+    match palette {
+        Some(palette) => palette.color(t * palette.scale as f64),
+        None => sine_color(t * std::f64::consts::TAU),
+    }
+}
+
+// every root of the Newton fractals gets its own color as in newton_color of the shader
+// darker the more steps it took to get there, black if it never converged
+pub fn root_color(root: Option<usize>, steps: i32, root_count: usize, palette: Option<&Palette>) -> [u8; 3] {
+    let root = match root {
+        Some(root) => root,
+        None => return [0, 0, 0],
+    };
+    let t = root as f64 / root_count as f64;
+    let base = match palette {
+        Some(palette) => palette.color(t * palette.scale as f64),
+        None => sine_color(t * std::f64::consts::TAU),
+    };
+    base.map(|channel| to_byte(channel as f64 / 255.0 / (1.0 + 0.05 * steps as f64)))
+}
+
+// the default colors of the shaders
+pub fn sine_color(n: f64) -> [u8; 3] {
+    [
+        to_byte(0.5 * n.sin() + 0.5),
+        to_byte(0.5 * (n + 2.094).sin() + 0.5),
+        to_byte(0.5 * (n + 4.188).sin() + 0.5),
+    ]
+}
+
+// normalized float to unsigned byte, the way GL writes to an 8 bit color buffer
+fn to_byte(value: f64) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
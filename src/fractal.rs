@@ -0,0 +1,169 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::fmt;
+use std::str::FromStr;
+
+use crate::shader::Shader;
+use crate::uniform::Uniform;
+
+// escape-time formulas z -> f(z) + c, the shader picks the same one through the `fractal` uniform
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Fractal {
+    Mandelbrot,
+    // z^d + c, d may be any real number
+    Multibrot(f64),
+    BurningShip,
+    Tricorn,
+    Celtic,
+    // root finding of a polynomial, see newton.rs, these are not escape-time fractals
+    Newton,
+    // the Newton method plus c
+    Nova,
+}
+
+impl Fractal {
+    // has to match the FRACTAL_* defines of the fragment shader
+    pub fn id(&self) -> i32 {
+        match self {
+            Fractal::Mandelbrot => 0,
+            Fractal::Multibrot(_) => 1,
+            Fractal::BurningShip => 2,
+            Fractal::Tricorn => 3,
+            Fractal::Celtic => 4,
+            Fractal::Newton => 5,
+            Fractal::Nova => 6,
+        }
+    }
+
+    pub fn power(&self) -> f64 {
+        match self {
+            Fractal::Multibrot(d) => *d,
+            _ => 2.0,
+        }
+    }
+
+    // the perturbation renderer only knows the orbit of z^2 + c
+    pub fn perturbation(&self) -> bool {
+        *self == Fractal::Mandelbrot
+    }
+
+    // colored by the root the Newton method ends up at instead of the escape time
+    pub fn newton(&self) -> bool {
+        matches!(self, Fractal::Newton | Fractal::Nova)
+    }
+
+    // the next formula in the order the key cycles through them
+    pub fn next(&self) -> Self {
+        match self {
+            Fractal::Mandelbrot => Fractal::Multibrot(3.0),
+            Fractal::Multibrot(_) => Fractal::BurningShip,
+            Fractal::BurningShip => Fractal::Tricorn,
+            Fractal::Tricorn => Fractal::Celtic,
+            Fractal::Celtic => Fractal::Newton,
+            Fractal::Newton => Fractal::Nova,
+            Fractal::Nova => Fractal::Mandelbrot,
+        }
+    }
+
+    // changes d of the Multibrot set, the others have no parameter
+    pub fn with_power(&self, d: f64) -> Self {
+        match self {
+            Fractal::Multibrot(_) => Fractal::Multibrot(d),
+            _ => *self,
+        }
+    }
+
+    // the running derivative |dz| after one more iteration from |z| = r, as derivative() in the shader
+    // by c for the Mandelbrot set, by z_0 for the Julia set
+    pub fn derivative(&self, r: f64, dr: f64, by_c: bool) -> f64 {
+        let power = self.power();
+        let growth = if r > 0.0 { power * r.powf(power - 1.0) } else { 0.0 };
+        growth * dr + if by_c { 1.0 } else { 0.0 }
+    }
+
+    // one iteration on the CPU, the same as in the shader
+    pub fn step(&self, z: glm::DVec2, c: glm::DVec2) -> glm::DVec2 {
+        let (x, y) = (z.x, z.y);
+        match self {
+            Fractal::Mandelbrot => glm::vec2(x * x - y * y, 2.0 * x * y) + c,
+            Fractal::Multibrot(d) => multibrot(z, *d) + c,
+            Fractal::BurningShip => glm::vec2(x * x - y * y, 2.0 * (x * y).abs()) + c,
+            Fractal::Tricorn => glm::vec2(x * x - y * y, -2.0 * x * y) + c,
+            Fractal::Celtic => glm::vec2((x * x - y * y).abs(), 2.0 * x * y) + c,
+            // iterated by Polynomial::newton instead
+            Fractal::Newton | Fractal::Nova => z,
+        }
+    }
+
+    pub fn set_uniforms(&self, shader: &mut Shader) {
+        Uniform::new("fractal", shader).seti(self.id(), shader);
+        Uniform::new("power", shader).setf(self.power() as f32, shader);
+    }
+}
+
+// integer powers by repeated multiplication, the rest in polar form
+fn multibrot(z: glm::DVec2, d: f64) -> glm::DVec2 {
+    if d == d.floor() && (1.0..=16.0).contains(&d) {
+        let mut result = z;
+        for _ in 1..d as i32 {
+            result = glm::vec2(result.x * z.x - result.y * z.y, result.x * z.y + result.y * z.x);
+        }
+        return result;
+    }
+    let r = glm::length(&z).powf(d);
+    let theta = z.y.atan2(z.x) * d;
+    glm::vec2(r * theta.cos(), r * theta.sin())
+}
+
+impl fmt::Display for Fractal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fractal::Mandelbrot => write!(f, "mandelbrot"),
+            Fractal::Multibrot(d) => write!(f, "multibrot:{}", d),
+            Fractal::BurningShip => write!(f, "burning-ship"),
+            Fractal::Tricorn => write!(f, "tricorn"),
+            Fractal::Celtic => write!(f, "celtic"),
+            Fractal::Newton => write!(f, "newton"),
+            Fractal::Nova => write!(f, "nova"),
+        }
+    }
+}
+
+// the names Display writes, multibrot takes its power after a colon
+impl FromStr for Fractal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (name, param) = match s.split_once(':') {
+            Some((name, param)) => (name, Some(param)),
+            None => (s, None),
+        };
+        match (name.to_lowercase().as_str(), param) {
+            ("mandelbrot", None) => Ok(Fractal::Mandelbrot),
+            ("multibrot", None) => Ok(Fractal::Multibrot(3.0)),
+            ("multibrot", Some(d)) => d.parse::<f64>()
+                .map(Fractal::Multibrot)
+                .map_err(|e| format!("Invalid multibrot power {}: {}", d, e)),
+            ("burning-ship", None) => Ok(Fractal::BurningShip),
+            ("tricorn", None) | ("mandelbar", None) => Ok(Fractal::Tricorn),
+            ("celtic", None) => Ok(Fractal::Celtic),
+            ("newton", None) => Ok(Fractal::Newton),
+            ("nova", None) => Ok(Fractal::Nova),
+            _ => Err(format!("Unknown fractal: {} (mandelbrot, multibrot[:d], burning-ship, tricorn, celtic, newton, nova)", s)),
+        }
+    }
+}
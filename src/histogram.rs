@@ -0,0 +1,208 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use gl::types::GLint;
+
+use crate::buffer::FrameBuffer;
+use crate::coloring::sine_color;
+use crate::palette::Palette;
+use crate::shader::Shader;
+use crate::texture::{InterpolationType, MapType, Texture, TextureType};
+use crate::uniform::Uniform;
+
+// the visible range of iteration counts is split into this many bins
+const BINS: usize = 1024;
+const ITERATIONS_UNIT: GLint = 2;
+const CDF_UNIT: GLint = 3;
+
+// cumulative distribution of the escaped iteration counts in a frame
+pub struct Histogram {
+    min: f32,
+    max: f32,
+    // share of the escaped pixels below the edges of the bins, BINS + 1 entries from 0 to 1
+    cdf: Vec<f32>,
+}
+
+// the fractal is first drawn into a float texture with the raw iteration counts, those are read
+// back for the histogram, then a second pass turns them into colors through the distribution
+pub struct HistogramPass {
+    shader: Shader,
+    framebuffer: FrameBuffer,
+    iterations: Texture,
+    cdf: Texture,
+    // range of the counts in the cdf
+    min_count: f32,
+    max_count: f32,
+}
+
+impl Histogram {
+    // negative counts mark the pixels that never escaped, they are left out
+    pub fn new(counts: &[f32]) -> Self {
+        let escaped = || counts.iter().copied().filter(|n| *n >= 0.0);
+        let min = escaped().fold(f32::INFINITY, f32::min);
+        let max = escaped().fold(f32::NEG_INFINITY, f32::max);
+        if min > max {
+            // nothing escaped
+            return Self {
+                min: 0.0,
+                max: 1.0,
+                cdf: (0..=BINS).map(|i| i as f32 / BINS as f32).collect(),
+            };
+        }
+        let mut histogram = Self { min, max, cdf: vec![0.0; BINS + 1] };
+        let mut total = 0.0;
+        for n in escaped() {
+            let bin = histogram.bin(n);
+            histogram.cdf[bin + 1] += 1.0;
+            total += 1.0;
+        }
+        for i in 1..=BINS {
+            histogram.cdf[i] += histogram.cdf[i - 1];
+        }
+        histogram.cdf.iter_mut().for_each(|share| *share /= total);
+        histogram
+    }
+
+    // position of n in [0, BINS]
+    fn position(&self, n: f32) -> f32 {
+        ((n - self.min) / (self.max - self.min).max(1e-6)).clamp(0.0, 1.0) * BINS as f32
+    }
+
+    fn bin(&self, n: f32) -> usize {
+        (self.position(n) as usize).min(BINS - 1)
+    }
+
+    // share of the escaped pixels with a lower count, 1 for the ones that never escaped
+    pub fn equalize(&self, n: f32) -> f32 {
+        if n < 0.0 {
+            return 1.0;
+        }
+        let position = self.position(n);
+        let i = (position.floor() as usize).min(BINS - 1);
+        let f = position - i as f32;
+        self.cdf[i] + (self.cdf[i + 1] - self.cdf[i]) * f
+    }
+
+    // the way histogram_frag.glsl colors the equalized count
+    pub fn color(&self, n: f32, palette: Option<&Palette>) -> [u8; 3] {
+        let t = self.equalize(n);
+        match palette {
+            Some(palette) => palette.color_at(t),
+            None => sine_color(std::f64::consts::PI * t as f64),
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.cdf.iter().flat_map(|share| share.to_ne_bytes()).collect()
+    }
+}
+
+impl HistogramPass {
+    pub fn new(vertex: &str, fragment: &str, width: i32, height: i32) -> Result<Self, String> {
+        let mut shader = Shader::new(vertex, fragment)?;
+        let iterations = Texture::from_data(&vec![0; (width * height * 4) as usize], width as u32, height as u32, ITERATIONS_UNIT, TextureType::R32F);
+        let framebuffer = FrameBuffer::new(&iterations, width, height)?;
+        let mut cdf = Texture::from_data(&[0; (BINS + 1) * 4], (BINS + 1) as u32, 1, CDF_UNIT, TextureType::R32F);
+        cdf.settings(InterpolationType::LINEAR, InterpolationType::LINEAR, MapType::CLAMP, MapType::CLAMP);
+        cdf.bind();
+        iterations.link(&mut shader, "iterations");
+        cdf.link(&mut shader, "cdf");
+        Ok(Self {
+            shader,
+            framebuffer,
+            iterations,
+            cdf,
+            min_count: 0.0,
+            max_count: 1.0,
+        })
+    }
+
+    // a new iteration texture for the new size of the window
+    pub fn resize(&mut self, width: i32, height: i32) -> Result<(), String> {
+        self.iterations = Texture::from_data(&vec![0; (width * height * 4) as usize], width as u32, height as u32, ITERATIONS_UNIT, TextureType::R32F);
+        self.framebuffer = FrameBuffer::new(&self.iterations, width, height)?;
+        Ok(())
+    }
+
+    // the fractal drawn after this goes into the iteration texture
+    pub fn begin(&mut self) {
+        self.framebuffer.bind();
+    }
+
+    // builds the histogram of the iteration texture and draws the colored image on the window
+    // while the texture still holds tiles of an older view, the cdf of the last whole picture is used
+    pub fn finish<F: FnMut()>(&mut self, palette: Option<&Palette>, stale: bool, mut draw: F) {
+        if !stale {
+            let histogram = Histogram::new(&self.framebuffer.read_red());
+            self.cdf.set_data(&histogram.bytes(), (BINS + 1) as u32, 1, TextureType::R32F);
+            self.min_count = histogram.min;
+            self.max_count = histogram.max;
+        }
+        self.framebuffer.unbind();
+        self.iterations.bind();
+
+        Uniform::new("min_count", &mut self.shader).setf(self.min_count, &mut self.shader);
+        Uniform::new("max_count", &mut self.shader).setf(self.max_count, &mut self.shader);
+        Palette::set_uniforms(palette, &mut self.shader);
+        self.shader.bind();
+        draw();
+        self.shader.unbind();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nothing_escaped() {
+        let histogram = Histogram::new(&[-1.0; 16]);
+        assert_eq!(histogram.equalize(-1.0), 1.0);
+        // an even spread over 0 to 1
+        assert_eq!(histogram.equalize(0.0), 0.0);
+        assert!((histogram.equalize(0.5) - 0.5).abs() < 1e-6);
+        assert_eq!(histogram.equalize(1.0), 1.0);
+    }
+
+    #[test]
+    fn even_counts() {
+        let counts: Vec<f32> = (0..100).map(|n| n as f32).chain([-1.0; 50]).collect();
+        let histogram = Histogram::new(&counts);
+        assert_eq!(histogram.equalize(0.0), 0.0);
+        assert_eq!(histogram.equalize(99.0), 1.0);
+        assert!((histogram.equalize(49.5) - 0.5).abs() < 0.02);
+        // never decreasing along the counts
+        let shares: Vec<f32> = (0..=990).map(|n| histogram.equalize(n as f32 / 10.0)).collect();
+        assert!(shares.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(histogram.equalize(-1.0), 1.0);
+    }
+
+    #[test]
+    fn crowded_counts() {
+        // most of the pixels have about the same count, they get most of the colors
+        let counts: Vec<f32> = [[1.0; 90].as_slice(), &[100.0; 10]].concat();
+        let histogram = Histogram::new(&counts);
+        assert!((histogram.equalize(50.0) - 0.9).abs() < 1e-6);
+        assert_eq!(histogram.equalize(100.0), 1.0);
+    }
+
+    #[test]
+    fn single_count() {
+        let histogram = Histogram::new(&[5.0, 5.0, -1.0]);
+        let share = histogram.equalize(5.0);
+        assert!((0.0..=1.0).contains(&share));
+    }
+}
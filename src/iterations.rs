@@ -0,0 +1,180 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+// the largest limit the automatic one goes up to, typed in ones may be higher
+const MAX_AUTO: i32 = 1 << 24;
+// share of the pixels that may hit the limit without escaping or falling into a cycle
+const UNRESOLVED: f64 = 0.001;
+// how far the last pictures moved the automatic limit away from the one of the zoom
+const MAX_BOOST: f64 = 1024.0;
+
+// what the orbits of a finished picture did with its iteration limit
+#[derive(Clone, Copy, Debug)]
+pub struct Statistics {
+    // share of the pixels that neither escaped nor fell into a cycle, they may need more iterations
+    pub unresolved: f64,
+    // the last iteration any of the other pixels escaped or found its cycle at
+    pub highest: i32,
+}
+
+// the iteration limit, typed in by hand or picked from the zoom and the statistics of the last picture
+#[derive(Clone, Copy, Debug)]
+pub struct IterationLimit {
+    pub auto: bool,
+    manual: i32,
+    boost: f64,
+}
+
+impl Statistics {
+    // `orbits` is the orbit state the shader saves for every pixel, as vec4(iter, dr, trap distance, period)
+    pub fn new(orbits: &[f32], max_iter: i32) -> Self {
+        let mut unresolved = 0;
+        let mut highest = 0;
+        for orbit in orbits.chunks_exact(4) {
+            let (iter, period) = (orbit[0] as i32, orbit[3] as i32);
+            if period == 0 && iter >= max_iter {
+                unresolved += 1;
+            } else {
+                highest = highest.max(iter);
+            }
+        }
+        Self {
+            unresolved: unresolved as f64 / (orbits.len() / 4).max(1) as f64,
+            highest,
+        }
+    }
+}
+
+impl IterationLimit {
+    pub fn new(auto: bool, manual: i32) -> Self {
+        Self {
+            auto,
+            manual,
+            boost: 1.0,
+        }
+    }
+
+    // more iterations the deeper the zoom, the boundary of the set gets more detailed there
+    pub fn for_zoom(mag: f64) -> i32 {
+        let depth = mag.log10().max(0.0);
+        (256.0 * (1.0 + depth).powf(1.5)) as i32
+    }
+
+    pub fn get(&self, mag: f64) -> i32 {
+        match self.auto {
+            true => ((Self::for_zoom(mag) as f64 * self.boost) as i32).clamp(2, MAX_AUTO),
+            false => self.manual,
+        }
+    }
+
+    // an exact limit by hand, the automatic one stops
+    pub fn set(&mut self, max_iter: i32) {
+        self.auto = false;
+        self.manual = max_iter.max(1);
+    }
+
+    // doubles or halves the limit, from the automatic one if that was on
+    pub fn scale(&mut self, mag: f64, up: bool) {
+        let current = self.get(mag);
+        self.set(if up { current.saturating_mul(2) } else { (current / 2).max(2) });
+    }
+
+    // the automatic limit starts over from the zoom when it is turned back on
+    pub fn toggle_auto(&mut self, mag: f64) {
+        match self.auto {
+            true => self.set(self.get(mag)),
+            false => {
+                self.auto = true;
+                self.boost = 1.0;
+            },
+        }
+    }
+
+    // follows the picture drawn with the current limit: doubles it when too many pixels hit it,
+    // and halves it when every pixel was done before a quarter of it, which halving keeps the same
+    pub fn adjust(&mut self, statistics: &Statistics, max_iter: i32) {
+        if !self.auto {
+            return;
+        }
+        if statistics.unresolved > UNRESOLVED && max_iter < MAX_AUTO {
+            self.boost = (self.boost * 2.0).min(MAX_BOOST);
+        } else if statistics.unresolved == 0.0 && statistics.highest < max_iter / 4 {
+            self.boost = (self.boost / 2.0).max(1.0 / MAX_BOOST);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the orbit state of pixels that stopped at these iterations, with these periods
+    fn orbits(pixels: &[(i32, i32)]) -> Vec<f32> {
+        pixels.iter().flat_map(|(iter, period)| [*iter as f32, 0.0, 0.0, *period as f32]).collect()
+    }
+
+    #[test]
+    fn statistics() {
+        let statistics = Statistics::new(&orbits(&[(10, 0), (256, 0), (256, 3), (40, 0)]), 256);
+        assert_eq!(statistics.unresolved, 0.25);
+        assert_eq!(statistics.highest, 256);
+        assert_eq!(Statistics::new(&[], 256).unresolved, 0.0);
+    }
+
+    #[test]
+    fn doubles_when_pixels_hit_the_limit() {
+        let mut limit = IterationLimit::new(true, 2);
+        let start = limit.get(1.0);
+        let hit = Statistics { unresolved: 0.01, highest: start };
+        limit.adjust(&hit, start);
+        assert_eq!(limit.get(1.0), start * 2);
+        limit.adjust(&hit, start * 2);
+        assert_eq!(limit.get(1.0), start * 4);
+        // up to the largest boost
+        for _ in 0..20 {
+            limit.adjust(&hit, limit.get(1.0));
+        }
+        assert_eq!(limit.get(1.0), (start as f64 * MAX_BOOST) as i32);
+    }
+
+    #[test]
+    fn halves_when_every_pixel_is_done_early() {
+        let mut limit = IterationLimit::new(true, 2);
+        let start = limit.get(1.0);
+        limit.adjust(&Statistics { unresolved: 0.0, highest: start / 4 - 1 }, start);
+        assert_eq!(limit.get(1.0), start / 2);
+        // a pixel past a quarter of the limit, or a few that hit it, keep it
+        limit.adjust(&Statistics { unresolved: 0.0, highest: start / 4 }, start / 2);
+        assert_eq!(limit.get(1.0), start / 2);
+        limit.adjust(&Statistics { unresolved: UNRESOLVED / 2.0, highest: 0 }, start / 2);
+        assert_eq!(limit.get(1.0), start / 2);
+    }
+
+    #[test]
+    fn manual_limit() {
+        let mut limit = IterationLimit::new(false, 300);
+        limit.adjust(&Statistics { unresolved: 1.0, highest: 300 }, 300);
+        assert_eq!(limit.get(1e10), 300);
+        limit.scale(1.0, true);
+        assert_eq!(limit.get(1.0), 600);
+        limit.set(i32::MAX);
+        limit.scale(1.0, true);
+        assert_eq!(limit.get(1.0), i32::MAX);
+        limit.toggle_auto(1.0);
+        assert_eq!(limit.get(1.0), IterationLimit::for_zoom(1.0));
+        assert!(IterationLimit::for_zoom(1e100) > IterationLimit::for_zoom(1e10));
+    }
+}
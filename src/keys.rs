@@ -0,0 +1,464 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::fmt;
+use std::fs;
+
+use sdl2::keyboard::{Keycode, Mod};
+
+// movement with the fine and the fast modifiers held down
+const FINE_SPEED: f64 = 0.25;
+const FAST_SPEED: f64 = 4.0;
+
+// what the keys do, bound to them in the key bindings file
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Forward,
+    Backward,
+    Left,
+    Right,
+    ZoomIn,
+    ZoomOut,
+    MoreIterations,
+    FewerIterations,
+    AutoIterations,
+    SetIterations,
+    Inertia,
+    GoTo,
+    SaveBookmark,
+    // the first nine bookmarks of the file
+    Bookmark(usize),
+    PreviousBookmark,
+    NextBookmark,
+    Julia,
+    Fractal,
+    PowerUp,
+    PowerDown,
+    FinePowerUp,
+    FinePowerDown,
+    Coloring,
+    Interior,
+    TrapShape,
+    RadiusUp,
+    RadiusDown,
+    Palette,
+    Buddhabrot,
+    SampleSize,
+    SamplePattern,
+    AddRoot,
+    RemoveRoot,
+    Fullscreen,
+    Screenshot,
+    Help,
+}
+
+// the name in the file, what the help says and the default keys
+const ACTIONS: [(Action, &str, &str, &[&str]); 44] = [
+    (Action::Forward, "forward", "move up", &["w"]),
+    (Action::Backward, "backward", "move down", &["s"]),
+    (Action::Left, "left", "move left", &["a"]),
+    (Action::Right, "right", "move right", &["d"]),
+    (Action::ZoomIn, "zoom-in", "zoom in", &["space"]),
+    (Action::ZoomOut, "zoom-out", "zoom out", &["-"]),
+    (Action::MoreIterations, "more-iterations", "double the iterations", &["q"]),
+    (Action::FewerIterations, "fewer-iterations", "halve the iterations", &["e"]),
+    (Action::AutoIterations, "auto-iterations", "automatic iterations", &["l"]),
+    (Action::SetIterations, "iterations", "type an iteration count", &["return", "keypad enter"]),
+    (Action::Inertia, "inertia", "inertia on/off", &["v"]),
+    (Action::GoTo, "go-to", "go to a point or bookmark", &["g"]),
+    (Action::SaveBookmark, "save-bookmark", "save a bookmark", &["k"]),
+    (Action::Bookmark(0), "bookmark-1", "bookmark 1", &["f1"]),
+    (Action::Bookmark(1), "bookmark-2", "bookmark 2", &["f2"]),
+    (Action::Bookmark(2), "bookmark-3", "bookmark 3", &["f3"]),
+    (Action::Bookmark(3), "bookmark-4", "bookmark 4", &["f4"]),
+    (Action::Bookmark(4), "bookmark-5", "bookmark 5", &["f5"]),
+    (Action::Bookmark(5), "bookmark-6", "bookmark 6", &["f6"]),
+    (Action::Bookmark(6), "bookmark-7", "bookmark 7", &["f7"]),
+    (Action::Bookmark(7), "bookmark-8", "bookmark 8", &["f8"]),
+    (Action::Bookmark(8), "bookmark-9", "bookmark 9", &["f9"]),
+    (Action::PreviousBookmark, "previous-bookmark", "previous bookmark", &["pageup"]),
+    (Action::NextBookmark, "next-bookmark", "next bookmark", &["pagedown"]),
+    (Action::Julia, "julia", "julia set of the cursor", &["j"]),
+    (Action::Fractal, "fractal", "next fractal", &["f"]),
+    (Action::PowerUp, "power-up", "power +1", &["]"]),
+    (Action::PowerDown, "power-down", "power -1", &["["]),
+    (Action::FinePowerUp, "fine-power-up", "power +0.05", &["."]),
+    (Action::FinePowerDown, "fine-power-down", "power -0.05", &[","]),
+    (Action::Coloring, "coloring", "next coloring", &["c"]),
+    (Action::Interior, "interior", "interior coloring", &["i"]),
+    (Action::TrapShape, "trap-shape", "next trap shape", &["o"]),
+    (Action::RadiusUp, "radius-up", "double the escape radius", &["r"]),
+    (Action::RadiusDown, "radius-down", "halve the escape radius", &["t"]),
+    (Action::Palette, "palette", "next palette", &["p"]),
+    (Action::Buddhabrot, "buddhabrot", "buddhabrot/anti/off", &["b"]),
+    (Action::SampleSize, "sample-size", "samples per pixel", &["z"]),
+    (Action::SamplePattern, "sample-pattern", "sample pattern", &["x"]),
+    (Action::AddRoot, "add-root", "add a root", &["n"]),
+    (Action::RemoveRoot, "remove-root", "remove the closest root", &["m"]),
+    (Action::Fullscreen, "fullscreen", "fullscreen on/off", &["f11"]),
+    (Action::Screenshot, "screenshot", "save a screenshot", &["f12"]),
+    (Action::Help, "help", "show/hide this help", &["h"]),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+// a key, pressed with the modifiers held down
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Chord {
+    modifiers: Modifiers,
+    key: Keycode,
+}
+
+// the actions of the keys, read from a file of `<action> <key>` lines on top of the defaults:
+//
+// # comment
+// forward z
+// zoom-out ctrl+space
+// fine shift
+// fast ctrl
+//
+// the first line of an action replaces its default keys, more lines add more keys and `none` leaves it without one,
+// fine and fast are the modifiers that slow down and speed up the movement
+pub struct Bindings {
+    pub path: String,
+    chords: Vec<(Chord, Action)>,
+    pub fine: Modifiers,
+    pub fast: Modifiers,
+}
+
+impl Action {
+    fn name(&self) -> &'static str {
+        ACTIONS.iter().find(|(action, ..)| action == self).map(|(_, name, ..)| *name).unwrap_or_default()
+    }
+}
+
+impl Modifiers {
+    pub fn from_mod(keymod: Mod) -> Self {
+        Self {
+            shift: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+            ctrl: keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD),
+            alt: keymod.intersects(Mod::LALTMOD | Mod::RALTMOD),
+        }
+    }
+
+    // the modifier a key is, pressing it alone already holds it down
+    fn of_key(key: Keycode) -> Self {
+        Self {
+            shift: matches!(key, Keycode::LShift | Keycode::RShift),
+            ctrl: matches!(key, Keycode::LCtrl | Keycode::RCtrl),
+            alt: matches!(key, Keycode::LAlt | Keycode::RAlt),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        !(self.shift || self.ctrl || self.alt)
+    }
+
+    fn contains(&self, other: Self) -> bool {
+        (self.shift || !other.shift) && (self.ctrl || !other.ctrl) && (self.alt || !other.alt)
+    }
+
+    fn count(&self) -> usize {
+        self.shift as usize + self.ctrl as usize + self.alt as usize
+    }
+
+    pub fn union(&self, other: Self) -> Self {
+        Self {
+            shift: self.shift || other.shift,
+            ctrl: self.ctrl || other.ctrl,
+            alt: self.alt || other.alt,
+        }
+    }
+
+    pub fn without(&self, other: Self) -> Self {
+        Self {
+            shift: self.shift && !other.shift,
+            ctrl: self.ctrl && !other.ctrl,
+            alt: self.alt && !other.alt,
+        }
+    }
+
+    // `shift`, `ctrl+alt`, ..., or `none`
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut modifiers = Self::default();
+        if text.trim() == "none" {
+            return Ok(modifiers);
+        }
+        for name in text.split('+') {
+            match name.trim().to_lowercase().as_str() {
+                "shift" => modifiers.shift = true,
+                "ctrl" | "control" => modifiers.ctrl = true,
+                "alt" => modifiers.alt = true,
+                _ => return Err(format!("Unknown modifier: {} (shift, ctrl, alt)", name)),
+            }
+        }
+        Ok(modifiers)
+    }
+}
+
+impl fmt::Display for Modifiers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = [(self.shift, "shift"), (self.ctrl, "ctrl"), (self.alt, "alt")];
+        let names: Vec<&str> = names.iter().filter(|(held, _)| *held).map(|(_, name)| *name).collect();
+        match names.is_empty() {
+            true => write!(f, "none"),
+            false => write!(f, "{}", names.join("+")),
+        }
+    }
+}
+
+impl Chord {
+    // the modifiers come first, `ctrl+shift+w`, the key by its SDL name in any case
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut modifiers = Modifiers::default();
+        let mut rest = text.trim();
+        while let Some((name, key)) = rest.split_once('+').filter(|(_, key)| !key.is_empty()) {
+            modifiers = modifiers.union(Modifiers::parse(name)?);
+            rest = key;
+        }
+        let key = Keycode::from_name(rest).ok_or(format!("Unknown key: {}", rest))?;
+        Ok(Self { modifiers, key })
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.modifiers.is_empty() {
+            true => write!(f, "{}", self.key.name()),
+            false => write!(f, "{}+{}", self.modifiers, self.key.name()),
+        }
+    }
+}
+
+impl Bindings {
+    // a file that doesn't exist leaves the default keys
+    pub fn load(path: &str) -> Result<Self, String> {
+        let mut bindings = Self {
+            path: path.to_owned(),
+            chords: Vec::new(),
+            fine: Modifiers { shift: true, ..Default::default() },
+            fast: Modifiers { ctrl: true, ..Default::default() },
+        };
+        for (action, _, _, keys) in ACTIONS.iter() {
+            for key in keys.iter() {
+                bindings.chords.push((Chord::parse(key)?, *action));
+            }
+        }
+        let src = match fs::read_to_string(path) {
+            Ok(src) => src,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("{}: {}", path, e)),
+        };
+        bindings.parse(&src).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(bindings)
+    }
+
+    fn parse(&mut self, src: &str) -> Result<(), String> {
+        let mut replaced = Vec::new();
+        for (number, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |e: String| format!("line {}: {}", number + 1, e);
+            let (name, value) = line.split_once(char::is_whitespace)
+                .map(|(name, value)| (name, value.trim()))
+                .ok_or_else(|| error(format!("expected an action and a key: {}", line)))?;
+            match name {
+                "fine" => self.fine = Modifiers::parse(value).map_err(error)?,
+                "fast" => self.fast = Modifiers::parse(value).map_err(error)?,
+                _ => {
+                    let action = ACTIONS.iter().find(|(_, action_name, ..)| *action_name == name).map(|(action, ..)| *action)
+                        .ok_or_else(|| error(format!("Unknown action: {}", name)))?;
+                    if !replaced.contains(&action) {
+                        self.chords.retain(|(_, bound)| *bound != action);
+                        replaced.push(action);
+                    }
+                    if value == "none" {
+                        continue;
+                    }
+                    let chord = Chord::parse(value).map_err(error)?;
+                    // a default key is taken over, one set in the file is an error
+                    if let Some(idx) = self.chords.iter().position(|(bound, _)| *bound == chord) {
+                        let bound = self.chords[idx].1;
+                        if replaced.contains(&bound) {
+                            return Err(error(format!("{} is bound to {} already", chord, bound.name())));
+                        }
+                        self.chords.remove(idx);
+                    }
+                    self.chords.push((chord, action));
+                },
+            }
+        }
+        Ok(())
+    }
+
+    // the action of a key pressed with the modifiers held down, the one with the most modifiers of them
+    // it comes with the modifiers that are part of it, they don't change the speed of the movement
+    pub fn action(&self, key: Keycode, modifiers: Modifiers) -> Option<(Action, Modifiers)> {
+        let modifiers = modifiers.without(Modifiers::of_key(key));
+        self.chords.iter()
+            .filter(|(chord, _)| chord.key == key && modifiers.contains(chord.modifiers))
+            .max_by_key(|(chord, _)| chord.modifiers.count())
+            .map(|(chord, action)| (*action, chord.modifiers.union(Modifiers::of_key(key))))
+    }
+
+    // how much faster the view moves with the modifiers held down
+    pub fn speed(&self, modifiers: Modifiers) -> f64 {
+        if !self.fast.is_empty() && modifiers.contains(self.fast) {
+            FAST_SPEED
+        } else if !self.fine.is_empty() && modifiers.contains(self.fine) {
+            FINE_SPEED
+        } else {
+            1.0
+        }
+    }
+
+    // the keys of an action, for the help and the terminal
+    pub fn keys(&self, action: Action) -> String {
+        let keys: Vec<String> = self.chords.iter().filter(|(_, bound)| *bound == action).map(|(chord, _)| chord.to_string()).collect();
+        match keys.is_empty() {
+            true => "-".to_owned(),
+            false => keys.join(" "),
+        }
+    }
+
+    // the lines of the help overlay
+    pub fn help(&self) -> Vec<String> {
+        let mut lines = vec![format!("keys ({})", self.path), String::new()];
+        for (action, name, description, _) in ACTIONS.iter() {
+            lines.push(format!("{:<19}{:<26}{}", name, description, self.keys(*action)));
+        }
+        lines.push(String::new());
+        lines.push(format!("{:<19}{:<26}{} / {}", "fine / fast", "slower / faster movement", self.fine, self.fast));
+        lines.push(format!("{:<19}{:<26}{}", "", "zoom at the cursor", "mouse wheel"));
+        lines.push(format!("{:<19}{:<26}{}", "", "move", "drag"));
+        lines.push(format!("{:<19}{:<26}{}", "", "zoom into a box", "shift+drag"));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONE: Modifiers = Modifiers { shift: false, ctrl: false, alt: false };
+    const SHIFT: Modifiers = Modifiers { shift: true, ctrl: false, alt: false };
+    const CTRL: Modifiers = Modifiers { shift: false, ctrl: true, alt: false };
+
+    // the defaults and the lines of a keys file on top of them
+    fn parsed(src: &str) -> Result<Bindings, String> {
+        let mut bindings = Bindings::load("/nonexistent/keys.txt")?;
+        bindings.parse(src)?;
+        Ok(bindings)
+    }
+
+    #[test]
+    fn defaults() {
+        let bindings = parsed("").unwrap();
+        assert_eq!(bindings.action(Keycode::W, NONE), Some((Action::Forward, NONE)));
+        assert_eq!(bindings.action(Keycode::Space, NONE), Some((Action::ZoomIn, NONE)));
+        assert_eq!(bindings.action(Keycode::Minus, NONE), Some((Action::ZoomOut, NONE)));
+        assert_eq!(bindings.action(Keycode::KpEnter, NONE), Some((Action::SetIterations, NONE)));
+        assert_eq!(bindings.action(Keycode::Y, NONE), None);
+        // every default key works the same with the speed modifiers held down, which only change the speed
+        for (chord, action) in bindings.chords.iter() {
+            assert!(!chord.modifiers.contains(bindings.fine) && !chord.modifiers.contains(bindings.fast), "{}", chord);
+            assert_eq!(bindings.action(chord.key, SHIFT).map(|(bound, _)| bound), Some(*action), "{}", chord);
+            assert_eq!(bindings.action(chord.key, CTRL).map(|(bound, _)| bound), Some(*action), "{}", chord);
+        }
+    }
+
+    #[test]
+    fn speed_modifiers() {
+        let bindings = parsed("").unwrap();
+        // a slow zoom in, shift is not part of the key
+        let (action, chord) = bindings.action(Keycode::Space, SHIFT).unwrap();
+        assert_eq!((action, bindings.speed(SHIFT.without(chord))), (Action::ZoomIn, FINE_SPEED));
+        assert_eq!(bindings.speed(CTRL), FAST_SPEED);
+        assert_eq!(bindings.speed(SHIFT.union(CTRL)), FAST_SPEED);
+        assert_eq!(bindings.speed(NONE), 1.0);
+        let swapped = parsed("fine alt\nfast none").unwrap();
+        assert_eq!(swapped.speed(Modifiers { alt: true, ..NONE }), FINE_SPEED);
+        assert_eq!(swapped.speed(CTRL), 1.0);
+    }
+
+    #[test]
+    fn chord_priority() {
+        let bindings = parsed("zoom-out ctrl+space\nscreenshot ctrl+shift+space").unwrap();
+        assert_eq!(bindings.action(Keycode::Space, NONE), Some((Action::ZoomIn, NONE)));
+        assert_eq!(bindings.action(Keycode::Space, SHIFT), Some((Action::ZoomIn, NONE)));
+        assert_eq!(bindings.action(Keycode::Space, CTRL), Some((Action::ZoomOut, CTRL)));
+        // the chord with the most of the held modifiers wins
+        assert_eq!(bindings.action(Keycode::Space, SHIFT.union(CTRL)).map(|(action, _)| action), Some(Action::Screenshot));
+        // and the ones left over change the speed
+        let alt = Modifiers { alt: true, ..CTRL };
+        let (action, chord) = bindings.action(Keycode::Space, alt).unwrap();
+        assert_eq!((action, alt.without(chord)), (Action::ZoomOut, Modifiers { alt: true, ..NONE }));
+        // the replaced default key is gone
+        assert_eq!(bindings.action(Keycode::Minus, NONE), None);
+    }
+
+    #[test]
+    fn modifier_keys() {
+        // pressing shift alone already holds shift down
+        let bindings = parsed("zoom-out left shift").unwrap();
+        assert_eq!(bindings.action(Keycode::LShift, SHIFT), Some((Action::ZoomOut, SHIFT)));
+    }
+
+    #[test]
+    fn parse_lines() {
+        let bindings = parsed("# AZERTY\nforward z\nforward f10\nleft q\nmore-iterations a\nhelp none\nfine shift+alt").unwrap();
+        assert_eq!(bindings.action(Keycode::Z, NONE).map(|(action, _)| action), Some(Action::Forward));
+        assert_eq!(bindings.action(Keycode::W, NONE), None);
+        assert_eq!(bindings.keys(Action::Forward), "Z F10");
+        // a default key is taken over by the action of the file
+        assert_eq!(bindings.action(Keycode::Q, NONE).map(|(action, _)| action), Some(Action::Left));
+        assert_eq!(bindings.keys(Action::MoreIterations), "A");
+        assert_eq!(bindings.keys(Action::Help), "-");
+        assert_eq!(bindings.fine, Modifiers { shift: true, alt: true, ctrl: false });
+    }
+
+    #[test]
+    fn parse_errors() {
+        for src in [
+            "forward",
+            "jump space",
+            "forward nokey",
+            "forward hyper+w",
+            "fine meta",
+            "forward z\nleft z",
+        ] {
+            assert!(parsed(src).is_err(), "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn chord_names() {
+        let chord = Chord::parse("Ctrl+Shift+w").unwrap();
+        assert_eq!((chord.modifiers, chord.key), (SHIFT.union(CTRL), Keycode::W));
+        assert_eq!(chord.to_string(), "shift+ctrl+W");
+        // a plus key is not a separator
+        assert_eq!(Chord::parse("shift++").unwrap().key, Keycode::Plus);
+        assert_eq!(Modifiers::parse("none").unwrap(), NONE);
+        assert_eq!(NONE.to_string(), "none");
+    }
+}
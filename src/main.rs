@@ -19,34 +19,98 @@ extern crate sdl2;
 extern crate gl;
 extern crate nalgebra_glm as glm;
 
-use sdl2::mouse::MouseUtil;
-use sdl2::keyboard::Keycode;
-use sdl2::video::Window;
-use sdl2::{event::Event, video::GLProfile};
+use sdl2::mouse::{MouseButton, MouseUtil, MouseWheelDirection};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::video::{FullscreenType, Window};
+use sdl2::{event::Event, event::WindowEvent, video::GLProfile};
 use gl::types::{GLfloat, GLuint, GLsizei};
 use std::ptr;
 
 mod shader;
-use shader::Shader;
 
 mod buffer;
 use buffer::{VertexArray, VertexBuffer, ElementBuffer, VertexAttribDescriptor, LayoutItem};
 
 mod uniform;
-use uniform::Uniform;
 
 mod texture;
 
 mod camera;
 use camera::{Camera, Movement};
 
+mod render;
+use render::Renderer;
+
+mod precision;
+use precision::{Precision, PrecisionShaders};
+
+mod bigfloat;
+use bigfloat::{BigFloat, BigVec2};
+
+mod perturbation;
+use perturbation::DeepRenderer;
+
+mod fractal;
+use fractal::Fractal;
+
+mod coloring;
+use coloring::{ColorMode, Coloring};
+
+mod palette;
+use palette::Palette;
+
+mod histogram;
+use histogram::HistogramPass;
+
+mod trap;
+use trap::{Trap, TrapImage, TrapShape};
+
+mod newton;
+use newton::Polynomial;
+
+mod buddhabrot;
+use buddhabrot::{Buddhabrot, BuddhabrotPass, DensityMode};
+
+mod sampling;
+use sampling::Supersampling;
+
+mod progressive;
+use progressive::Progressive;
+
+mod iterations;
+use iterations::{IterationLimit, Statistics};
+
+mod motion;
+use motion::Motion;
+
+mod bookmarks;
+use bookmarks::{Bookmark, Bookmarks};
+
+mod cli;
+use cli::{Command, Options};
+
+mod keys;
+use keys::{Action, Bindings, Modifiers};
+
+mod font;
+
+mod overlay;
+use overlay::Overlay;
+
+mod screenshot;
+use screenshot::Screenshots;
+
 // mod mesh;
 // use mesh::Mesh;
 
-const WIDTH: f32 = 1920.0;
-const HEIGHT: f32 = 1080.0;
+// zoom of one step of the mouse wheel
+const WHEEL_ZOOM: f64 = 1.25;
+// a shift-drag smaller than this many pixels is a click, not a box to zoom into
+const MIN_BOX: f32 = 4.0;
+// the window can't be made smaller than this
+const MIN_WINDOW: (u32, u32) = (320, 200);
 
-fn viewport(position: &glm::Vec2, mag: f32) -> glm::Mat3 {
+fn viewport(position: &glm::DVec2, mag: f64) -> glm::DMat3 {
     let mut transform = glm::mat3(
         1.0, 0.0, 0.0,
         0.0, 1.0, 0.0,
@@ -64,19 +128,218 @@ fn viewport(position: &glm::Vec2, mag: f32) -> glm::Mat3 {
     transform
 }
 
+// tex_coord of a window pixel, from -1 to 1 on both axes with y up
+fn screen_to_tex(x: f32, y: f32, size: (f32, f32)) -> glm::DVec2 {
+    glm::vec2(x as f64 / size.0 as f64 * 2.0 - 1.0, 1.0 - y as f64 / size.1 as f64 * 2.0)
+}
+
+// the point of the complex plane under a window pixel, in full precision
+fn screen_to_complex(pos: &BigVec2, mvp: &glm::DMat3, x: f32, y: f32, size: (f32, f32)) -> BigVec2 {
+    let tex = screen_to_tex(x, y, size);
+    let linear = glm::mat2(
+        mvp[(0, 0)], mvp[(0, 1)],
+        mvp[(1, 0)], mvp[(1, 1)],
+    );
+    center_of(pos, size).offset(&(linear * tex))
+}
+
+// outline of a rectangle between two window pixels (y down), on top of the picture
+fn draw_box(x0: f32, y0: f32, x1: f32, y1: f32, height: f32) {
+    let (left, right) = (x0.min(x1) as i32, x0.max(x1) as i32);
+    let (bottom, top) = ((height - y0.max(y1)) as i32, (height - y0.min(y1)) as i32);
+    let (width, height) = ((right - left).max(1), (top - bottom).max(1));
+    let edges = [
+        (left, bottom, width, 1),
+        (left, top - 1, width, 1),
+        (left, bottom, 1, height),
+        (right - 1, bottom, 1, height),
+    ];
+    unsafe {
+        gl::Enable(gl::SCISSOR_TEST);
+        gl::ClearColor(1.0, 1.0, 1.0, 1.0);
+        for (x, y, width, height) in edges {
+            gl::Scissor(x, y, width, height);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+        }
+        gl::Disable(gl::SCISSOR_TEST);
+    }
+}
+
+// `x,y` or `x,y,mag` typed in, a point of the complex plane to put in the middle of the view
+// returns the position before the projection, and the magnification that stays `mag` if it wasn't given
+fn parse_target(text: &str, mag: f64, size: (f32, f32)) -> Result<(BigVec2, f64), String> {
+    let values: Vec<&str> = text.split(',').map(str::trim).collect();
+    let mag = match values[..] {
+        [_, _] => mag,
+        [_, _, mag] => mag.parse::<f64>().ok().filter(|mag| *mag > 0.0).ok_or(format!("Expected a positive magnification: {}", mag))?,
+        _ => return Err(format!("Expected x,y or x,y,mag: {}", text)),
+    };
+    let limbs = bigfloat::limbs_for(mag);
+    let center = BigVec2 {
+        x: BigFloat::parse_decimal(values[0], limbs)?,
+        y: BigFloat::parse_decimal(values[1], limbs)?,
+    };
+    Ok((position_of(&center, size), mag))
+}
+
+// the point of the complex plane in the middle of the view at `pos`, exact where projection() scales by a double
+fn center_of(pos: &BigVec2, size: (f32, f32)) -> BigVec2 {
+    BigVec2 {
+        x: pos.x.mul_ratio(size.0 as u32, size.1 as u32),
+        y: pos.y.clone(),
+    }
+}
+
+// the position that puts a point of the complex plane in the middle of the view
+fn position_of(center: &BigVec2, size: (f32, f32)) -> BigVec2 {
+    BigVec2 {
+        x: center.x.mul_ratio(size.1 as u32, size.0 as u32),
+        y: center.y.clone(),
+    }
+}
+
+// the position of a given center, or the default view of the whole set, which is placed before the
+// projection stretches it to the window, so its center is -0.5 times the aspect ratio
+fn start_position(center: Option<&BigVec2>, mag: f64, size: (f32, f32)) -> BigVec2 {
+    match center {
+        Some(center) => position_of(center, size),
+        None => BigVec2::new(-0.5, 0.0, bigfloat::limbs_for(mag)),
+    }
+}
+
+fn projection(width: f64, height: f64) -> glm::DMat3 {
+    glm::mat3(
+        width / height, 0.0, 0.0,
+        0.0, 1.0, 0.0,
+        0.0, 0.0, 0.0)
+}
+
+// the view the program starts with, from the command line or a bookmark
+struct StartView {
+    center: Option<BigVec2>,
+    mag: f64,
+    julia: Option<BigVec2>,
+    fractal: Fractal,
+    iterations: Option<IterationLimit>,
+    palette: Option<usize>,
+}
+
+// renders the start view into a picture on the CPU
+// the renderer comes with the options of the command line, the view is set here
+fn render_headless(output: &str, options: &Options, start: &StartView, mut renderer: Renderer) -> Result<(), String> {
+    let (width, height) = options.size;
+    let size = (width as f32, height as f32);
+    let mvp = projection(width as f64, height as f64) * viewport(&start_position(start.center.as_ref(), start.mag, size).to_f64(), start.mag);
+    if let Some(mode) = options.density {
+        return Buddhabrot::new(width, height).write_png(mode, start.fractal, options.limits, &mvp, options.samples, output);
+    }
+    // there is no picture before this one to adjust the automatic limit with, it only follows the zoom
+    let iter = match start.iterations {
+        Some(limit) => limit.get(start.mag),
+        None => 256,
+    };
+    renderer
+        .size(width, height)
+        .mvp(&mvp)
+        .max_iter(iter)
+        .fractal(start.fractal)
+        .julia(start.julia.as_ref().map(BigVec2::to_f64))
+        .write_png(output)
+}
+
 fn main() -> Result<(), String> {
+    let (command, options) = cli::parse(std::env::args().skip(1).collect())?;
+    if let Command::Help = command {
+        print!("{}", cli::USAGE);
+        return Ok(());
+    }
+    let mut fractal = options.fractal;
+    let mut polynomial = options.polynomial.clone();
+    let mut coloring = options.coloring;
+    let mut supersampling = options.supersampling;
+    let mut density_mode = options.density;
+    let limits = options.limits;
+    // the picture of the image trap, on the CPU for the headless mode and as a texture for the window
+    let trap_image = match &options.trap_image {
+        Some(file) => Some((TrapImage::load(file)?, file.clone())),
+        None => None,
+    };
+    let palette_dir = options.palette_dir();
+    let mut palettes = match std::path::Path::new(&palette_dir).is_dir() {
+        true => Palette::load_dir(&palette_dir)?,
+        false => Vec::new(),
+    };
+    // a palette file, or the name of one from the palette directory
+    let palette = match &options.palette {
+        Some(file) if std::path::Path::new(file).is_file() => {
+            palettes.push(Palette::load(file)?);
+            Some(palettes.len() - 1)
+        },
+        Some(name) => Some(palettes.iter().position(|palette| palette.name == *name)
+            .ok_or(format!("Unknown palette: {} ({})", name, palettes.iter().map(|palette| palette.name.as_str()).collect::<Vec<_>>().join(", ")))?),
+        None => None,
+    };
+    // saved views, recalled with the keys
+    let mut bookmarks = Bookmarks::load(&options.bookmarks)?;
+    let mut start = StartView {
+        center: options.center.clone(),
+        mag: options.zoom,
+        julia: options.julia.clone(),
+        fractal,
+        iterations: options.iterations,
+        palette,
+    };
+    if let Some(name) = &options.bookmark {
+        let saved = bookmarks.find(name).ok_or(format!("No bookmark named {} in {}", name, options.bookmarks))?;
+        start.center = Some(saved.center.clone());
+        start.mag = saved.mag;
+        start.julia = saved.julia.clone();
+        start.fractal = saved.fractal;
+        // --iterations still wins over the one of the bookmark
+        start.iterations = options.iterations.or(Some(match saved.iterations {
+            Some(count) => IterationLimit::new(false, count),
+            None => IterationLimit::new(true, 2),
+        }));
+        start.palette = saved.palette.as_ref().and_then(|name| palettes.iter().position(|palette| palette.name == *name));
+    }
+    if let Command::Render(output) = &command {
+        let mut renderer = Renderer::new(options.size.0, options.size.1);
+        renderer
+            .polynomial(polynomial)
+            .coloring(coloring)
+            .palette(start.palette.map(|idx| palettes[idx].clone()))
+            .trap_image(trap_image.map(|(image, _)| image))
+            .supersampling(supersampling);
+        return render_headless(output, &options, &start, renderer);
+    }
+
     let sdl_context = sdl2::init()?;
     let video_subsys = sdl_context.video()?;
+    let bindings = Bindings::load(&options.keys)?;
+    // the keys of the first bookmarks, for the list on the terminal
+    let bookmark_keys: Vec<String> = (0..9).map(|idx| bindings.keys(Action::Bookmark(idx))).collect();
     
     // attributes for the GL
     let gl_attr = video_subsys.gl_attr();
     gl_attr.set_context_profile(GLProfile::Core);
     gl_attr.set_context_version(3, 3);
+    // the deep zoom renderer marks the finished pixels in it
+    gl_attr.set_stencil_size(8);
 
-    let window = video_subsys.window("Mandelbrot fractal", WIDTH as u32, HEIGHT as u32)
+    let mut window_builder = video_subsys.window("Mandelbrot fractal", options.size.0, options.size.1);
+    window_builder
         .position_centered()
-        .opengl()   // this one is actually very important
-        .build().map_err(|e| e.to_string())?;
+        .resizable()
+        .allow_highdpi()
+        .opengl();   // this one is actually very important
+    if options.fullscreen {
+        window_builder.fullscreen_desktop();
+    }
+    let mut window = window_builder.build().map_err(|e| e.to_string())?;
+    window.set_minimum_size(MIN_WINDOW.0, MIN_WINDOW.1).map_err(|e| e.to_string())?;
+    // the desktop decides the size of a fullscreen window, and the pixels of a high DPI one are more than its size
+    let (mut width, mut height) = window.drawable_size();
+    let mut size = (width as f32, height as f32);
     
     // let mouse = &sdl_context.mouse();
 
@@ -84,6 +347,9 @@ fn main() -> Result<(), String> {
     let _ctx = window.gl_create_context()?;
     // some hella unsafe raw pointery stuff
     gl::load_with(|name| video_subsys.gl_get_proc_address(name) as *const _);
+    // sdl starts out typing text, it is only wanted for the prompts
+    let text_input = video_subsys.text_input();
+    text_input.stop();
 
     // --------------------------------------------------------------
     let vertices : [GLfloat;16] = [
@@ -97,8 +363,24 @@ fn main() -> Result<(), String> {
         0, 1, 2,
         0, 2, 3,
     ];
-    let mut shader = Shader::new("resources/shader/mandelbrot_vert.glsl","resources/shader/mandelbrot_frag.glsl")?;
-    shader.bind();
+    let shader_file = |name: &str| format!("{}/{}", options.shader_dir, name);
+    let vertex = shader_file("mandelbrot_vert.glsl");
+    let mut shaders = PrecisionShaders::new(&vertex, &shader_file("mandelbrot_frag.glsl"))?;
+    let mut deep = DeepRenderer::new(&vertex, &shader_file("perturbation_frag.glsl"), width as i32, height as i32)?;
+    let mut histogram = HistogramPass::new(&vertex, &shader_file("histogram_frag.glsl"), width as i32, height as i32)?;
+    let mut progressive = Progressive::new(width as i32, height as i32)?;
+    let mut buddhabrot = Buddhabrot::new(width, height);
+    let mut buddhabrot_pass = BuddhabrotPass::new(&vertex, &shader_file("buddhabrot_frag.glsl"), width as i32, height as i32)?;
+    // the keys, shown over the picture with the help key
+    let mut help = Overlay::new(&vertex, &shader_file("overlay_frag.glsl"), width as i32, height as i32)?;
+    help.set_text(&bindings.help());
+    // the lookup texture of the selected palette, it stays bound to its own unit
+    let mut palette = start.palette;
+    let mut palette_texture = palette.map(|idx| palettes[idx].texture());
+    let _trap_texture = match &trap_image {
+        Some((image, file)) => Some(image.texture(file)?),
+        None => None,
+    };
     let mut vao = VertexArray::new();
     let mut vbo = VertexBuffer::new(&mut vao);
     vbo.set_data(&vertices);
@@ -111,7 +393,6 @@ fn main() -> Result<(), String> {
     vao.unbind();
     vbo.unbind();
     ebo.unbind(&mut vao);
-    shader.unbind();
     
 
     let _unit = glm::mat3(
@@ -121,78 +402,370 @@ fn main() -> Result<(), String> {
     );
 
     let model = _unit;
-    let mut pos = glm::vec2(-0.5, 0.0);
-    let mut mag: f32 = 1.0;
+    fractal = start.fractal;
+    let mut mag = start.mag;
+    let mut pos = start_position(start.center.as_ref(), mag, size);
+    // the view of the set not on the screen, Mandelbrot and Julia keep their own
+    let mut other_mag: f64 = 1.0;
+    let mut other_pos = match start.julia {
+        Some(_) => start_position(None, other_mag, size),
+        None => BigVec2::new(0.0, 0.0, bigfloat::limbs_for(other_mag)),
+    };
+    let mut julia = start.julia;
+    let mut limit = start.iterations.unwrap_or(IterationLimit::new(false, 2));
 
-    let mut view = viewport(&pos, mag);
-    let projection = glm::mat3(
-        WIDTH / HEIGHT, 0.0, 0.0,  
-        0.0, 1.0, 0.0,
-        0.0, 0.0, 0.0);
-    let mut mvp = projection * view * model;
-    let mvp_uniform = Uniform::new("mvp",&mut shader);
-    mvp_uniform.set_m3f(&mvp, &mut shader);
+    let mut view;
+    let mut mvp;
 
-    let mut iter = 2;
-    let iter_uniform = Uniform::new("max_iter",&mut shader);
-    iter_uniform.seti(iter,&mut shader);
+    let mut title = String::new();
+    // shown in the title, what happened to the last bookmark or typed point
+    let mut status: Option<String> = None;
+    // the bookmark that was recalled last, the keys step on from it
+    let mut bookmark: Option<usize> = None;
+    if !bookmarks.list.is_empty() {
+        bookmarks.print(&bookmark_keys);
+    }
+    let mut motion = Motion::new(options.inertia);
+    let mut screenshots = Screenshots::default();
 
     let mut evt_pump = sdl_context.event_pump()?;
     let mut t1 = std::time::Instant::now();
     let mut t2: std::time::Instant;
-    let mut evt_manager = EvtManager::new();
+    let mut evt_manager = EvtManager::new(size, window.size(), bindings);
     'active: loop {
         t2 = std::time::Instant::now();
         let dt = t2 - t1;
         t1 = t2;
         // positioning
-        view = viewport(&pos, mag);
+        pos.set_precision(bigfloat::limbs_for(mag));
+        view = viewport(&pos.to_f64(), mag);
+        let projection = projection(width as f64, height as f64);
         mvp = projection * view * model;
+        let iter = limit.get(mag);
         // mvp = view * model;
-        mvp_uniform.set_m3f(&mvp, &mut shader);
-        iter_uniform.seti(iter, &mut shader);
+        let mut precision = Precision::required(&mvp, height as f64, shaders.native_double());
+        if precision == Precision::Perturbation && !fractal.perturbation() {
+            precision = Precision::Double;
+        }
+        let precision = shaders.available(precision);
 
         // drawing
 
-        unsafe {
-            gl::ClearColor(0.0,0.0,0.0,1.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT);
+        // the Newton fractals have no orbits to trace, and no escape time to equalize
+        let density = density_mode.filter(|_| !fractal.newton());
+        if density.is_none() {
+            buddhabrot.stop();
+        }
+        let equalize = coloring.mode == ColorMode::Histogram && !fractal.newton() && density.is_none();
+        // the escape time shaders draw in tiles, into a target that keeps the tiles of the previous frames
+        let tiled = density.is_none() && precision != Precision::Perturbation;
+        let mut tiled_mvp = mvp;
+        if tiled {
+            // everything the picture depends on but the view and the iteration limit, the tiles start over when any of it changes
+            let key = (precision, julia.clone(), fractal, polynomial.clone(), coloring, palette, supersampling, equalize);
+            // the orbits are saved for one sample per pixel, not for the image trap that remembers a color
+            let resumable = !equalize && supersampling.size == 1 && !fractal.newton()
+                && (coloring.mode != ColorMode::Trap || coloring.trap.shape != TrapShape::Image);
+            // panning moves the picture along and only draws the strips that came into view,
+            // the iteration counts of the histogram are not kept for it
+            tiled_mvp = progressive.update(key, &mvp, iter, resumable, !equalize);
+        }
+        if equalize {
+            histogram.begin();
+        } else if tiled {
+            progressive.begin();
+        }
+        if !tiled {
+            unsafe {
+                gl::ClearColor(0.0,0.0,0.0,1.0);
+                gl::Clear(gl::COLOR_BUFFER_BIT);
+            }
+        }
+        let current_palette = palette.map(|idx| &palettes[idx]);
+        if let Some(mode) = density {
+            // keeps tracing in the background, the picture fills in over the frames
+            shaders.unbind();
+            buddhabrot.start(mode, fractal, limits, &mvp);
+            buddhabrot_pass.draw(&buddhabrot, || {
+                vao.bind();
+                unsafe {
+                    gl::DrawElements(gl::TRIANGLES, indices.len() as GLsizei, gl::UNSIGNED_INT, ptr::null() as *const _);
+                }
+            });
+        } else if precision == Precision::Perturbation {
+            shaders.unbind();
+            deep.set_palette(current_palette);
+            deep.set_supersampling(&supersampling);
+            let center = pos.transform(&(projection * model));
+            deep.draw(&center, &mvp, iter, julia.as_ref(), &coloring, || {
+                vao.bind();
+                unsafe {
+                    gl::DrawElements(gl::TRIANGLES, indices.len() as GLsizei, gl::UNSIGNED_INT, ptr::null() as *const _);
+                }
+            });
+        } else {
+            let shader = shaders.get(precision);
+            // within half a pixel of mvp, on the pixels of the picture that was moved along
+            precision.set_view(&tiled_mvp, shader);
+            precision.set_julia(julia.as_ref(), shader);
+            fractal.set_uniforms(shader);
+            polynomial.set_uniforms(shader);
+            coloring.set_uniforms(shader);
+            Palette::set_uniforms(current_palette, shader);
+            supersampling.set_uniforms(shader);
+            progressive.set_uniforms(shader);
+            shader.bind();
+            progressive.draw(|| {
+                vao.bind();
+                unsafe {
+                    gl::DrawElements(gl::TRIANGLES, indices.len() as GLsizei, gl::UNSIGNED_INT, ptr::null() as *const _);
+                }
+            });
+            if !equalize {
+                progressive.finish();
+            }
+        }
+        if equalize {
+            shaders.unbind();
+            // the tiles of the old view are only all overwritten once the progressive pass is done
+            histogram.finish(current_palette, tiled && !progressive.done(), || {
+                vao.bind();
+                unsafe {
+                    gl::DrawElements(gl::TRIANGLES, indices.len() as GLsizei, gl::UNSIGNED_INT, ptr::null() as *const _);
+                }
+            });
+        }
+        // the picture without the box and the help drawn over it
+        if evt_manager.screenshot {
+            status = Some(match screenshots.save(width, height) {
+                Ok(file) => format!("saved {}", file),
+                Err(e) => e,
+            });
+            evt_manager.screenshot = false;
+        }
+        if let Some((x0, y0, x1, y1)) = evt_manager.selection() {
+            draw_box(x0, y0, x1, y1, size.1);
         }
-        shader.bind();
-        vao.bind();
-        unsafe {
-            gl::DrawElements(gl::TRIANGLES, indices.len() as GLsizei, gl::UNSIGNED_INT, ptr::null() as *const _);
+        if evt_manager.help {
+            shaders.unbind();
+            help.draw(|| {
+                vao.bind();
+                unsafe {
+                    gl::DrawElements(gl::TRIANGLES, indices.len() as GLsizei, gl::UNSIGNED_INT, ptr::null() as *const _);
+                }
+            });
         }
         window.gl_swap_window();
+        // the automatic limit follows what the orbits of the finished picture did
+        if limit.auto && tiled && !equalize && !fractal.newton() {
+            if let Some((pass_iter, orbits)) = progressive.finished() {
+                if pass_iter == iter {
+                    limit.adjust(&Statistics::new(&orbits, iter), iter);
+                }
+            }
+        }
+        let current_title = match &evt_manager.typing {
+            Some((Prompt::Target, text)) => format!("Mandelbrot fractal - go to x,y or x,y,mag or a bookmark: {}_", text),
+            Some((Prompt::Bookmark, text)) => format!("Mandelbrot fractal - bookmark name: {}_", text),
+            Some((Prompt::Iterations, text)) => format!("Mandelbrot fractal - iterations: {}_", text),
+            None => format!("Mandelbrot fractal - {} iterations{}{}", iter,
+                if limit.auto { " (auto)" } else { "" },
+                status.as_ref().map(|status| format!(" - {}", status)).unwrap_or_default()),
+        };
+        if current_title != title {
+            window.set_title(&current_title).map_err(|e| e.to_string())?;
+            title = current_title;
+        }
+        // the keyboard only types text while a prompt is open
+        if evt_manager.typing.is_some() != text_input.is_active() {
+            match evt_manager.typing.is_some() {
+                true => text_input.start(),
+                false => text_input.stop(),
+            }
+        }
 
         for evt in evt_pump.poll_iter() {
             match evt {
                 Event::Quit {..} => break 'active,
+                Event::Window { win_event: WindowEvent::Resized(..) | WindowEvent::SizeChanged(..), .. } => evt_manager.resized = true,
                 Event::KeyDown {
                     timestamp: _,
                     keycode,
-                    keymod: _,
-                    repeat: _,
+                    keymod,
+                    repeat,
                     window_id: _,
                     scancode: _,
-                } => evt_manager.key_down(keycode),
+                } => evt_manager.key_down(keycode, keymod, repeat),
                 Event::KeyUp {
                     timestamp: _,
                     keycode,
-                    keymod: _,
+                    keymod,
                     repeat: _,
                     window_id: _,
                     scancode: _,
-                } => evt_manager.key_up(keycode),
+                } => evt_manager.key_up(keycode, keymod),
+                Event::TextInput { text, .. } => evt_manager.text_input(&text),
+                Event::MouseMotion { x, y, .. } => evt_manager.mouse_position(x, y),
+                Event::MouseButtonDown { mouse_btn, .. } => evt_manager.mouse_button(mouse_btn, true),
+                Event::MouseButtonUp { mouse_btn, .. } => evt_manager.mouse_button(mouse_btn, false),
+                Event::MouseWheel { y, direction, .. } => evt_manager.mouse_wheel(match direction {
+                    MouseWheelDirection::Flipped => -y,
+                    _ => y,
+                }),
                 _ => {},
             }
         }
-        evt_manager.update_pos(&mut pos, &mut mag, &mut iter, dt.as_secs_f32());
+        if evt_manager.fullscreen {
+            let state = match window.fullscreen_state() {
+                FullscreenType::Off => FullscreenType::Desktop,
+                _ => FullscreenType::Off,
+            };
+            window.set_fullscreen(state)?;
+            evt_manager.fullscreen = false;
+            evt_manager.resized = true;
+        }
+        if evt_manager.resized {
+            let drawable = window.drawable_size();
+            if drawable != (width, height) && drawable.0 > 0 && drawable.1 > 0 {
+                let resized = (drawable.0 as f32, drawable.1 as f32);
+                // the middle of the view stays in the middle, the aspect ratio changes around it
+                pos = position_of(&center_of(&pos, size), resized);
+                other_pos = position_of(&center_of(&other_pos, size), resized);
+                motion.stop();
+                (width, height) = drawable;
+                size = resized;
+                unsafe {
+                    gl::Viewport(0, 0, width as i32, height as i32);
+                }
+                deep.resize(width as i32, height as i32);
+                histogram.resize(width as i32, height as i32)?;
+                progressive.resize(width as i32, height as i32)?;
+                buddhabrot = Buddhabrot::new(width, height);
+                help.resize(width as i32, height as i32);
+            }
+            evt_manager.resize(size, window.size());
+            evt_manager.resized = false;
+        }
+        if evt_manager.julia {
+            // c is the point under the mouse, picked in the Mandelbrot view
+            julia = match julia {
+                Some(_) => None,
+                None => Some(screen_to_complex(&pos, &mvp, evt_manager.mouse_x, evt_manager.mouse_y, size)),
+            };
+            std::mem::swap(&mut pos, &mut other_pos);
+            std::mem::swap(&mut mag, &mut other_mag);
+            motion.stop();
+            evt_manager.julia = false;
+        }
+        let mut palette_changed = false;
+        // a bookmark picked by its key, by name, or stepped to
+        let mut recall = evt_manager.bookmark_key.take()
+            .and_then(|idx| bookmarks.list.get(idx).map(|_| idx));
+        if evt_manager.bookmark_step != 0 && !bookmarks.list.is_empty() {
+            let count = bookmarks.list.len() as i32;
+            let current = bookmark.map_or(if evt_manager.bookmark_step > 0 { -1 } else { 0 }, |idx| idx as i32);
+            recall = Some((current + evt_manager.bookmark_step).rem_euclid(count) as usize);
+        }
+        evt_manager.bookmark_step = 0;
+        match evt_manager.typed.take() {
+            Some((Prompt::Target, text)) => match parse_target(&text, mag, size) {
+                Ok((target, target_mag)) => motion.go_to(&pos, mag, target, target_mag),
+                Err(e) => match bookmarks.list.iter().position(|bookmark| bookmark.name.eq_ignore_ascii_case(text.trim())) {
+                    Some(idx) => recall = Some(idx),
+                    None => status = Some(e),
+                },
+            },
+            Some((Prompt::Iterations, text)) => match text.parse::<i32>() {
+                Ok(count) if count > 0 => limit.set(count),
+                _ => status = Some(format!("not an iteration count: {}", text)),
+            },
+            Some((Prompt::Bookmark, name)) if !name.trim().is_empty() => {
+                let saved = Bookmark {
+                    name: name.trim().to_owned(),
+                    center: center_of(&pos, size),
+                    mag,
+                    iterations: (!limit.auto).then_some(iter),
+                    fractal,
+                    palette: palette.map(|idx| palettes[idx].name.clone()),
+                    julia: julia.clone(),
+                };
+                status = Some(match bookmarks.add(saved) {
+                    Ok(()) => format!("saved {}", name.trim()),
+                    Err(e) => e,
+                });
+                bookmarks.print(&bookmark_keys);
+            },
+            _ => {},
+        }
+        if let Some(idx) = recall {
+            let target = &bookmarks.list[idx];
+            // the same picture flies to the place, another fractal or set is switched to at once
+            let same_set = target.fractal == fractal && target.julia == julia;
+            if target.julia.is_some() != julia.is_some() {
+                // the view of the other set is kept for j
+                other_pos = pos.clone();
+                other_mag = mag;
+            }
+            fractal = target.fractal;
+            julia = target.julia.clone();
+            match target.iterations {
+                Some(count) => limit.set(count),
+                None if !limit.auto => limit.toggle_auto(target.mag),
+                None => {},
+            }
+            palette = target.palette.as_ref().and_then(|name| palettes.iter().position(|palette| palette.name == *name));
+            palette_changed = true;
+            if same_set {
+                motion.go_to(&pos, mag, position_of(&target.center, size), target.mag);
+            } else {
+                motion.stop();
+                pos = position_of(&target.center, size);
+                mag = target.mag;
+            }
+            status = Some(format!("{} ({}/{})", target.name, idx + 1, bookmarks.list.len()));
+            bookmark = Some(idx);
+        }
+        match bookmarks.reload() {
+            Ok(true) => bookmarks.print(&bookmark_keys),
+            Ok(false) => {},
+            Err(e) => status = Some(e),
+        }
+        evt_manager.update_pos(&mut motion, &mut pos, &mut mag, dt.as_secs_f64());
+        evt_manager.update_iterations(&mut limit, mag);
+        evt_manager.update_fractal(&mut fractal);
+        evt_manager.update_coloring(&mut coloring);
+        evt_manager.update_density(&mut density_mode);
+        evt_manager.update_supersampling(&mut supersampling);
+        // the mouse edits the roots of the Newton fractals, and the trap of the others
+        let mouse = screen_to_complex(&pos, &mvp, evt_manager.mouse_x, evt_manager.mouse_y, size);
+        let selecting = evt_manager.selection().is_some();
+        if fractal.newton() && !selecting {
+            evt_manager.update_roots(&mut polynomial, mouse.to_f64());
+        } else if coloring.mode == ColorMode::Trap && !selecting {
+            evt_manager.update_trap(&mut coloring.trap, mouse.to_f64(), trap_image.is_some());
+        }
+        evt_manager.update_navigation(&mut motion, &mut pos, &mut mag, fractal.newton() || coloring.mode == ColorMode::Trap);
+        if evt_manager.update_palette(&mut palette, palettes.len()) || palette_changed {
+            if let Some(idx) = palette {
+                match palette_texture.as_mut() {
+                    Some(texture) => palettes[idx].upload(texture),
+                    None => palette_texture = Some(palettes[idx].texture()),
+                }
+            }
+        }
         ::std::thread::sleep(::std::time::Duration::new(0, 1_000_000_000u32 / 60));
     }
     Ok(())
 }
 
+// what the typed text is for
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Prompt {
+    Target,
+    Bookmark,
+    Iterations,
+}
+
 #[allow(unused)]
 struct EvtManager {
     forward:  bool,
@@ -203,15 +776,70 @@ struct EvtManager {
     down:     bool,
     inc:      bool,
     dec:      bool,
+    julia:    bool,
+    auto_iter: bool,
+    // the text of a prompt while it is typed, and then once enter was pressed
+    typing:   Option<(Prompt, String)>,
+    typed:    Option<(Prompt, String)>,
+    // the keys of the first nine bookmarks recall them, the previous and next keys step through them
+    bookmark_key:  Option<usize>,
+    bookmark_step: i32,
+    inertia:  bool,
+    help:     bool,
+    fullscreen: bool,
+    screenshot: bool,
+    // the window changed its size, or went fullscreen
+    resized:  bool,
+
+    fractal:    bool,
+    power_up:   bool,
+    power_down: bool,
+    fine_up:    bool,
+    fine_down:  bool,
+
+    coloring:      bool,
+    interior:      bool,
+    trap_shape:    bool,
+    // dragging with the left button moves the trap or a root, with the right one sizes and turns the trap
+    drag_left:     bool,
+    drag_right:    bool,
+    // dragging with the middle button, or the left one when it edits nothing, moves the view
+    drag_middle:   bool,
+    // where the drag started and where the cursor was in the last frame, window pixels
+    drag_start:    Option<(f32, f32)>,
+    drag_last:     (f32, f32),
+    // the drag started with shift held down, it draws a box to zoom into
+    selecting:     bool,
+    wheel:         i32,
+    palette:       bool,
+    density:       bool,
+    sample_size:   bool,
+    sample_pattern: bool,
+    radius_up:     bool,
+    radius_down:   bool,
+
+    add_root:      bool,
+    remove_root:   bool,
+    // the root under the cursor when the drag started
+    dragged_root:  Option<usize>,
 
     mouse: bool,
     mouse_x: f32,
     mouse_y: f32,
+    // of the window in pixels, and the pixels per point of the mouse
+    size: (f32, f32),
+    pixel_ratio: (f32, f32),
+
+    bindings:  Bindings,
+    modifiers: Modifiers,
+    // the keys held down, with the action they started and the modifiers that were part of it
+    held:      Vec<(Keycode, Action, Modifiers)>,
 }
         
 #[allow(unused)]
 impl EvtManager {
-    fn new() -> Self {
+    // `points` is the size of the window for the mouse, it is smaller than the pixels on a high DPI display
+    fn new(size: (f32, f32), points: (u32, u32), bindings: Bindings) -> Self {
         Self {
             forward:  false,
             backward: false,
@@ -222,39 +850,177 @@ impl EvtManager {
 
             inc:      false,
             dec:      false,
+            julia:    false,
+            auto_iter: false,
+            typing:   None,
+            typed:    None,
+            bookmark_key:  None,
+            bookmark_step: 0,
+            inertia:  false,
+            help:     false,
+            fullscreen: false,
+            screenshot: false,
+            resized:  false,
+
+            fractal:    false,
+            power_up:   false,
+            power_down: false,
+            fine_up:    false,
+            fine_down:  false,
+
+            coloring:      false,
+            interior:      false,
+            trap_shape:    false,
+            drag_left:     false,
+            drag_right:    false,
+            drag_middle:   false,
+            drag_start:    None,
+            drag_last:     (0.0, 0.0),
+            selecting:     false,
+            wheel:         0,
+            palette:       false,
+            density:       false,
+            sample_size:   false,
+            sample_pattern: false,
+            radius_up:     false,
+            radius_down:   false,
+
+            add_root:      false,
+            remove_root:   false,
+            dragged_root:  None,
 
             mouse:    false,
             mouse_x: 0.0,
             mouse_y: 0.0,
+            size,
+            pixel_ratio: (size.0 / points.0 as f32, size.1 / points.1 as f32),
+
+            bindings,
+            modifiers: Modifiers::default(),
+            held:      Vec::new(),
         }
     }
 
-    fn key_state_set(&mut self, key: Option<Keycode>, state: bool) {
-        use sdl2::keyboard::Keycode::*;
-        match key {
-            Some(code) => {
-                match code {
-                    W      => self.forward  = state,
-                    S      => self.backward = state,
-                    A      => self.left     = state,
-                    D      => self.right    = state,
-                    Q      => self.inc      = state,
-                    E      => self.dec      = state,
-                    Space  => self.up       = state,
-                    LShift => self.down     = state,
-                    _ => {},
-                };
+    fn key_state_set(&mut self, action: Action, state: bool) {
+        match action {
+            Action::Forward         => self.forward  = state,
+            Action::Backward        => self.backward = state,
+            Action::Left            => self.left     = state,
+            Action::Right           => self.right    = state,
+            Action::MoreIterations  => self.inc      = state,
+            Action::FewerIterations => self.dec      = state,
+            Action::Julia           => self.julia    = state,
+            Action::AutoIterations  => self.auto_iter = state,
+            Action::Inertia         => self.inertia  = state,
+            Action::Fractal         => self.fractal    = state,
+            Action::PowerUp         => self.power_up   = state,
+            Action::PowerDown       => self.power_down = state,
+            Action::FinePowerUp     => self.fine_up    = state,
+            Action::FinePowerDown   => self.fine_down  = state,
+            Action::Coloring        => self.coloring    = state,
+            Action::Interior        => self.interior    = state,
+            Action::TrapShape       => self.trap_shape  = state,
+            Action::RadiusUp        => self.radius_up   = state,
+            Action::RadiusDown      => self.radius_down = state,
+            Action::Palette         => self.palette     = state,
+            Action::Buddhabrot      => self.density     = state,
+            Action::SampleSize      => self.sample_size = state,
+            Action::SamplePattern   => self.sample_pattern = state,
+            Action::AddRoot         => self.add_root    = state,
+            Action::RemoveRoot      => self.remove_root = state,
+            Action::ZoomIn          => self.up       = state,
+            Action::ZoomOut         => self.down     = state,
+            // these happen once for every press
+            Action::GoTo | Action::SaveBookmark | Action::SetIterations | Action::Bookmark(_)
+                | Action::PreviousBookmark | Action::NextBookmark | Action::Fullscreen | Action::Screenshot | Action::Help => {},
+        }
+    }
+
+    fn key_up(&mut self, key: Option<Keycode>, keymod: Mod) {
+        self.modifiers = Modifiers::from_mod(keymod);
+        let released: Vec<Action> = self.held.iter().filter(|(held, ..)| Some(*held) == key).map(|(_, action, _)| *action).collect();
+        self.held.retain(|(held, ..)| Some(*held) != key);
+        for action in released {
+            self.key_state_set(action, false);
+        }
+    }
+
+    // a held key repeats, only the movement and the zoom go on with it, the other actions happen once per press
+    fn key_down(&mut self, key: Option<Keycode>, keymod: Mod, repeat: bool) {
+        self.modifiers = Modifiers::from_mod(keymod);
+        if self.typing.is_some() {
+            self.type_text(key);
+            return;
+        }
+        let code = match key {
+            Some(code) => code,
+            None => return,
+        };
+        if code == Keycode::Escape {
+            self.help = false;
+        }
+        let (action, chord) = match self.bindings.action(code, self.modifiers) {
+            Some(bound) => bound,
+            None => return,
+        };
+        let held = matches!(action, Action::Forward | Action::Backward | Action::Left | Action::Right | Action::ZoomIn | Action::ZoomOut);
+        if repeat && !held {
+            return;
+        }
+        match action {
+            Action::GoTo => self.typing = Some((Prompt::Target, String::new())),
+            Action::SaveBookmark => self.typing = Some((Prompt::Bookmark, String::new())),
+            Action::SetIterations => self.typing = Some((Prompt::Iterations, String::new())),
+            Action::Bookmark(idx) => self.bookmark_key = Some(idx),
+            Action::PreviousBookmark => self.bookmark_step -= 1,
+            Action::NextBookmark => self.bookmark_step += 1,
+            Action::Help => self.help = !self.help,
+            Action::Fullscreen => self.fullscreen = true,
+            Action::Screenshot => self.screenshot = true,
+            _ => {
+                if !self.held.iter().any(|(held, ..)| *held == code) {
+                    self.held.push((code, action, chord));
+                }
+                self.key_state_set(action, true)
             },
-            None => {}
         }
     }
 
-    fn key_up(&mut self, key: Option<Keycode>) {
-        self.key_state_set(key, false)
+    // how fast a held action moves the view, with the modifiers held down that are not part of its key
+    fn speed(&self, action: Action) -> f64 {
+        match self.held.iter().find(|(_, held, _)| *held == action) {
+            Some((_, _, chord)) => self.bindings.speed(self.modifiers.without(*chord)),
+            None => 0.0,
+        }
+    }
+
+    // the keys that edit the text of a prompt: enter is done, escape gives up and backspace takes back a character
+    // the characters themselves come from text_input, in the layout of the keyboard
+    fn type_text(&mut self, key: Option<Keycode>) {
+        let text = match self.typing.as_mut() {
+            Some((_, text)) => text,
+            None => return,
+        };
+        match key {
+            Some(Keycode::Return) | Some(Keycode::KpEnter) => self.typed = self.typing.take(),
+            Some(Keycode::Escape) => self.typing = None,
+            Some(Keycode::Backspace) => {
+                text.pop();
+            },
+            _ => {},
+        }
     }
 
-    fn key_down(&mut self, key: Option<Keycode>) {
-        self.key_state_set(key, true)
+    // what the keyboard typed while a prompt is open, the iteration count only takes digits
+    fn text_input(&mut self, typed: &str) {
+        match self.typing.as_mut() {
+            Some((Prompt::Iterations, text)) => {
+                text.extend(typed.chars().filter(char::is_ascii_digit));
+                text.truncate(9);
+            },
+            Some((_, text)) => text.extend(typed.chars().filter(|c| !c.is_control())),
+            None => {},
+        }
     }
 
     fn mouse_up(&mut self, mouse: &MouseUtil) {
@@ -267,11 +1033,48 @@ impl EvtManager {
         mouse.show_cursor(false);
     }
 
+    fn mouse_button(&mut self, button: MouseButton, state: bool) {
+        match button {
+            MouseButton::Left => self.drag_left = state,
+            MouseButton::Right => self.drag_right = state,
+            MouseButton::Middle => self.drag_middle = state,
+            _ => {},
+        }
+        if state && matches!(button, MouseButton::Left | MouseButton::Middle) && self.drag_start.is_none() {
+            self.drag_start = Some((self.mouse_x, self.mouse_y));
+            self.drag_last = (self.mouse_x, self.mouse_y);
+            self.selecting = button == MouseButton::Left && self.modifiers.shift;
+        }
+    }
+
+    fn mouse_wheel(&mut self, y: i32) {
+        self.wheel += y;
+    }
+
+    // the corners of the box being drawn with shift-drag
+    fn selection(&self) -> Option<(f32, f32, f32, f32)> {
+        match self.drag_start {
+            Some((x, y)) if self.selecting && self.drag_left => Some((x, y, self.mouse_x, self.mouse_y)),
+            _ => None,
+        }
+    }
+
+    // the cursor in pixels
+    fn mouse_position(&mut self, x: i32, y: i32) {
+        self.mouse_x = x as f32 * self.pixel_ratio.0;
+        self.mouse_y = y as f32 * self.pixel_ratio.1;
+    }
+
+    fn resize(&mut self, size: (f32, f32), points: (u32, u32)) {
+        self.size = size;
+        self.pixel_ratio = (size.0 / points.0 as f32, size.1 / points.1 as f32);
+    }
+
     fn mouse_movement(&mut self, xrel: f32, yrel: f32, window: &Window, mouse: &MouseUtil) {
         if self.mouse {
             self.mouse_x = xrel;
             self.mouse_y = yrel;
-            mouse.warp_mouse_in_window(window, (WIDTH / 2.0) as i32, (HEIGHT / 2.0) as i32);
+            mouse.warp_mouse_in_window(window, (self.size.0 / self.pixel_ratio.0 / 2.0) as i32, (self.size.1 / self.pixel_ratio.1 / 2.0) as i32);
         }
     }
 
@@ -300,39 +1103,182 @@ impl EvtManager {
         }
     }
 
-    fn update_pos(&mut self, pos: &mut glm::Vec2, mag: &mut f32, iter: &mut i32, dt: f32) {
-        // let inc = 1.0 / (*mag * 10.0);
-        // let mag_inc = *mag / 10.0;
-        let inc = dt / *mag;
-        let mag_inc = *mag * dt;
-        if self.forward {
-            pos.y += inc;
+    // the keys give the view a speed, the motion keeps some of it after they are let go
+    fn update_pos(&mut self, motion: &mut Motion, pos: &mut BigVec2, mag: &mut f64, dt: f64) {
+        // the fine and fast modifiers change the speed
+        let direction = glm::vec2(
+            self.speed(Action::Right) - self.speed(Action::Left),
+            self.speed(Action::Forward) - self.speed(Action::Backward),
+        );
+        let zoom = self.speed(Action::ZoomIn) - self.speed(Action::ZoomOut);
+        if self.inertia {
+            motion.inertia = !motion.inertia;
+            self.inertia = false;
         }
-        if self.backward {
-            pos.y -= inc;
-        }
-        if self.left {
-            pos.x -= inc;
+        motion.update(pos, mag, direction, zoom, dt);
+        if *mag < 0.001 {
+            *mag = 0.001
         }
-        if self.right {
-            pos.x += inc;
+    }
+
+    // the wheel zooms keeping the point under the cursor in place, dragging moves the view along with the cursor
+    // and the box of a shift-drag is zoomed into when the button is let go
+    // editing tells if the left button edits the roots or the trap instead
+    fn update_navigation(&mut self, motion: &mut Motion, pos: &mut BigVec2, mag: &mut f64, editing: bool) {
+        // pos + tex / mag is the point under tex before the projection
+        let cursor = screen_to_tex(self.mouse_x, self.mouse_y, self.size);
+        if self.wheel != 0 {
+            motion.stop();
+            let zoomed = (*mag * WHEEL_ZOOM.powi(self.wheel)).max(0.001);
+            *pos = pos.offset(&(cursor * (1.0 / *mag - 1.0 / zoomed)));
+            *mag = zoomed;
+            self.wheel = 0;
         }
-        if self.up {
-            *mag += mag_inc;
+        let (start_x, start_y) = match self.drag_start {
+            Some(start) => start,
+            None => return,
+        };
+        if self.selecting {
+            if self.drag_left {
+                return;
+            }
+            if (self.mouse_x - start_x).abs() > MIN_BOX && (self.mouse_y - start_y).abs() > MIN_BOX {
+                let corner = screen_to_tex(start_x, start_y, self.size);
+                let size = glm::abs(&(cursor - corner));
+                // the whole box fits, the view is 2 wide and high in tex_coord
+                motion.go_to(pos, *mag, pos.offset(&((corner + cursor) / 2.0 / *mag)), *mag * 2.0 / size.x.max(size.y));
+            }
+        } else if self.drag_middle || (self.drag_left && !editing) {
+            motion.stop();
+            let last = screen_to_tex(self.drag_last.0, self.drag_last.1, self.size);
+            *pos = pos.offset(&((last - cursor) / *mag));
+            self.drag_last = (self.mouse_x, self.mouse_y);
         }
-        if self.down {
-            *mag -= mag_inc;
+        if !self.drag_left && !self.drag_middle {
+            self.drag_start = None;
+            self.selecting = false;
         }
-        if self.inc && *iter < 1024 {
-            *iter *= 2; 
+    }
+
+    // q and e double and halve the limit, l turns the automatic one on and off
+    fn update_iterations(&mut self, limit: &mut IterationLimit, mag: f64) {
+        if self.inc || self.dec {
+            limit.scale(mag, self.inc);
             self.inc = false;
-        }
-        if self.dec && *iter > 2 {
-            *iter /= 2;
             self.dec = false;
         }
-        if *mag < 0.001 {
-            *mag = 0.001
+        if self.auto_iter {
+            limit.toggle_auto(mag);
+            self.auto_iter = false;
+        }
+    }
+
+    fn update_fractal(&mut self, fractal: &mut Fractal) {
+        if self.fractal {
+            *fractal = fractal.next();
+            self.fractal = false;
+        }
+        // whole steps for the power of the Multibrot set, and fine ones for real powers
+        let step = match (self.power_up, self.power_down, self.fine_up, self.fine_down) {
+            (true, _, _, _) => 1.0,
+            (_, true, _, _) => -1.0,
+            (_, _, true, _) => 0.05,
+            (_, _, _, true) => -0.05,
+            _ => return,
+        };
+        *fractal = fractal.with_power(((fractal.power() + step) * 100.0).round() / 100.0);
+        self.power_up = false;
+        self.power_down = false;
+        self.fine_up = false;
+        self.fine_down = false;
+    }
+
+    fn update_coloring(&mut self, coloring: &mut Coloring) {
+        if self.coloring {
+            *coloring = coloring.toggle();
+            self.coloring = false;
+        }
+        if self.interior {
+            coloring.interior = coloring.interior.toggle();
+            self.interior = false;
+        }
+        if self.radius_up && coloring.escape_radius < 65536.0 {
+            coloring.escape_radius *= 2.0;
+            self.radius_up = false;
+        }
+        if self.radius_down && coloring.escape_radius > 2.0 {
+            coloring.escape_radius = (coloring.escape_radius / 2.0).max(2.0);
+            self.radius_down = false;
+        }
+    }
+
+    // mouse is the point under the cursor, image tells if the image trap is available
+    fn update_trap(&mut self, trap: &mut Trap, mouse: glm::DVec2, image: bool) {
+        if self.trap_shape {
+            trap.shape = trap.shape.next(image);
+            self.trap_shape = false;
+        }
+        if self.drag_left {
+            trap.center = mouse;
+        }
+        if self.drag_right {
+            let offset = mouse - trap.center;
+            trap.size = glm::length(&offset).max(1e-12);
+            trap.angle = offset.y.atan2(offset.x);
+        }
+    }
+
+    // off, Buddhabrot, Anti-Buddhabrot
+    fn update_density(&mut self, mode: &mut Option<DensityMode>) {
+        if self.density {
+            *mode = DensityMode::next(*mode);
+            self.density = false;
+        }
+    }
+
+    // 1x1 to 4x4 samples, and the pattern they are placed in
+    fn update_supersampling(&mut self, supersampling: &mut Supersampling) {
+        if self.sample_size {
+            *supersampling = supersampling.next_size();
+            self.sample_size = false;
+        }
+        if self.sample_pattern {
+            supersampling.pattern = supersampling.pattern.next();
+            self.sample_pattern = false;
+        }
+    }
+
+    // mouse is the point under the cursor, new roots go there and the closest one follows a drag
+    fn update_roots(&mut self, polynomial: &mut Polynomial, mouse: glm::DVec2) {
+        if self.add_root {
+            polynomial.add_root(mouse);
+        }
+        if self.remove_root {
+            polynomial.remove_root(polynomial.nearest_root(mouse));
+        }
+        self.add_root = false;
+        self.remove_root = false;
+        if !self.drag_left {
+            self.dragged_root = None;
+            return;
+        }
+        let idx = *self.dragged_root.get_or_insert_with(|| polynomial.nearest_root(mouse));
+        polynomial.roots[idx] = mouse;
+    }
+
+    // index into the loaded palettes, None after the last one is the sine coloring again
+    // true when the selection changed
+    fn update_palette(&mut self, palette: &mut Option<usize>, count: usize) -> bool {
+        if !self.palette || count == 0 {
+            self.palette = false;
+            return false;
         }
+        *palette = match *palette {
+            Some(idx) if idx + 1 < count => Some(idx + 1),
+            Some(_) => None,
+            None => Some(0),
+        };
+        self.palette = false;
+        true
     }
 }
@@ -0,0 +1,232 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::bigfloat::{self, BigVec2};
+
+// time for the speed left by the keys to fall to 1/e after they are let go, in seconds
+const DAMPING: f64 = 0.25;
+// slower than this the view stops, in view heights and e-folds of the zoom per second
+const MIN_SPEED: f64 = 1e-3;
+const MAX_DURATION: f64 = 4.0;
+
+// an eased flight from one view to another
+struct Transition {
+    from: BigVec2,
+    from_mag: f64,
+    to: BigVec2,
+    to_mag: f64,
+    // to - from, small enough for a double in the units of the smaller magnification
+    delta: glm::DVec2,
+    elapsed: f64,
+    duration: f64,
+}
+
+// moves the view with the speed the keys give, what is left of it after they are let go,
+// and the flights to a target view
+pub struct Motion {
+    // the view keeps going after the keys are let go, slowing down
+    pub inertia: bool,
+    // view units per second, one is half the height of the window
+    velocity: glm::DVec2,
+    // ln(mag) per second
+    zoom_rate: f64,
+    transition: Option<Transition>,
+}
+
+impl Transition {
+    fn new(from: &BigVec2, from_mag: f64, to: BigVec2, to_mag: f64) -> Self {
+        let delta = glm::vec2((&to.x - &from.x).to_f64(), (&to.y - &from.y).to_f64());
+        // longer for deeper zooms and for longer ways across the screen
+        let zoom = (to_mag / from_mag).ln().abs();
+        let distance = (glm::length(&delta) * from_mag.min(to_mag)).ln_1p();
+        // the whole way in the precision of the deeper view
+        let limbs = bigfloat::limbs_for(from_mag.max(to_mag));
+        let mut from = from.clone();
+        from.set_precision(limbs);
+        let mut to = to;
+        to.set_precision(limbs);
+        Self {
+            from,
+            from_mag,
+            to,
+            to_mag,
+            delta,
+            elapsed: 0.0,
+            duration: (0.4 + 0.2 * (zoom + distance)).min(MAX_DURATION),
+        }
+    }
+
+    // the view at the time, the zoom goes exponentially so that it looks like a constant speed
+    fn at(&self, elapsed: f64) -> (BigVec2, f64) {
+        let t = (elapsed / self.duration).clamp(0.0, 1.0);
+        // eased in and out
+        let s = t * t * (3.0 - 2.0 * t);
+        let mag = self.from_mag * (self.to_mag / self.from_mag).powf(s);
+        // the position follows 1 / mag, which keeps the point the two views share in place on the screen
+        // weight is the part of the way done and remaining the rest, each one exact where it is small
+        let (weight, remaining) = match (self.to_mag / self.from_mag).ln().abs() > 1e-9 {
+            true => {
+                let span = 1.0 / self.to_mag - 1.0 / self.from_mag;
+                ((1.0 / mag - 1.0 / self.from_mag) / span, (1.0 / self.to_mag - 1.0 / mag) / span)
+            },
+            false => (s, 1.0 - s),
+        };
+        // delta only has the precision of a double, measured from the nearer end it is off by less than a pixel
+        match weight <= 0.5 {
+            true => (self.from.offset(&(self.delta * weight)), mag),
+            false => (self.to.offset(&(-self.delta * remaining)), mag),
+        }
+    }
+}
+
+impl Motion {
+    pub fn new(inertia: bool) -> Self {
+        Self {
+            inertia,
+            velocity: glm::vec2(0.0, 0.0),
+            zoom_rate: 0.0,
+            transition: None,
+        }
+    }
+
+    // flies from the current view to the target
+    pub fn go_to(&mut self, pos: &BigVec2, mag: f64, target: BigVec2, target_mag: f64) {
+        self.stop();
+        self.transition = Some(Transition::new(pos, mag, target, target_mag));
+    }
+
+    // the view stays where it is, for the mouse that moves it directly
+    pub fn stop(&mut self) {
+        self.velocity = glm::vec2(0.0, 0.0);
+        self.zoom_rate = 0.0;
+        self.transition = None;
+    }
+
+    // `direction` is where the keys move the view in view units per second and `zoom` how fast they zoom,
+    // both zero when no key is held down
+    pub fn update(&mut self, pos: &mut BigVec2, mag: &mut f64, direction: glm::DVec2, zoom: f64, dt: f64) {
+        if direction != glm::vec2(0.0, 0.0) || zoom != 0.0 {
+            self.transition = None;
+            self.velocity = direction;
+            self.zoom_rate = zoom;
+        } else if let Some(transition) = self.transition.as_mut() {
+            transition.elapsed += dt;
+            (*pos, *mag) = transition.at(transition.elapsed);
+            if transition.elapsed >= transition.duration {
+                *pos = transition.to.clone();
+                *mag = transition.to_mag;
+                self.transition = None;
+            }
+            return;
+        } else if self.inertia {
+            let decay = (-dt / DAMPING).exp();
+            self.velocity *= decay;
+            self.zoom_rate *= decay;
+            if glm::length(&self.velocity) < MIN_SPEED && self.zoom_rate.abs() < MIN_SPEED {
+                self.stop();
+            }
+        } else {
+            self.stop();
+        }
+        if self.velocity != glm::vec2(0.0, 0.0) {
+            *pos = pos.offset(&(self.velocity * dt / *mag));
+        }
+        *mag *= (self.zoom_rate * dt).exp();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bigfloat::BigFloat;
+
+    fn point(x: &str, y: &str, mag: f64) -> BigVec2 {
+        let limbs = bigfloat::limbs_for(mag);
+        BigVec2 {
+            x: BigFloat::parse_decimal(x, limbs).unwrap(),
+            y: BigFloat::parse_decimal(y, limbs).unwrap(),
+        }
+    }
+
+    // how far apart two points are, in screens of the magnification
+    fn screens(a: &BigVec2, b: &BigVec2, mag: f64) -> f64 {
+        let delta = glm::vec2((&a.x - &b.x).to_f64(), (&a.y - &b.y).to_f64());
+        glm::length(&delta) * mag
+    }
+
+    #[test]
+    fn endpoints() {
+        let from = point("-0.5", "0", 1.0);
+        let to = point("-0.743643887037158704752191506114774", "0.131825904205311970493132056385139", 1e30);
+        let transition = Transition::new(&from, 1.0, to.clone(), 1e30);
+        let (start, start_mag) = transition.at(0.0);
+        assert_eq!(start_mag, 1.0);
+        assert!(screens(&start, &from, 1.0) < 1e-12);
+        let (end, end_mag) = transition.at(transition.duration);
+        assert_eq!(end_mag, 1e30);
+        assert_eq!(end, transition.to);
+        // close to the end it is still within a fraction of the screen of the target
+        let (near, near_mag) = transition.at(transition.duration * 0.999);
+        assert!(screens(&near, &to, near_mag) < 0.5, "{}", screens(&near, &to, near_mag));
+    }
+
+    #[test]
+    fn monotonic_zoom() {
+        let from = point("0", "0", 1.0);
+        for (from_mag, to_mag) in [(1.0, 1e20), (1e20, 2.0), (5.0, 5.0)] {
+            let transition = Transition::new(&from, from_mag, point("0.25", "-0.5", to_mag), to_mag);
+            let mags: Vec<f64> = (0..=100).map(|i| transition.at(transition.duration * i as f64 / 100.0).1).collect();
+            let rising = mags.windows(2).all(|pair| pair[1] >= pair[0]);
+            let falling = mags.windows(2).all(|pair| pair[1] <= pair[0]);
+            assert!(if to_mag >= from_mag { rising } else { falling }, "{} to {}", from_mag, to_mag);
+        }
+    }
+
+    #[test]
+    fn flight_lands_on_the_target() {
+        let mut motion = Motion::new(true);
+        let mut pos = point("-0.5", "0", 1.0);
+        let mut mag = 1.0;
+        let target = point("0.001", "0.002", 1e6);
+        motion.go_to(&pos, mag, target.clone(), 1e6);
+        for _ in 0..100 {
+            motion.update(&mut pos, &mut mag, glm::vec2(0.0, 0.0), 0.0, 0.1);
+        }
+        assert_eq!(mag, 1e6);
+        assert!(screens(&pos, &target, mag) < 1e-9);
+    }
+
+    #[test]
+    fn inertia() {
+        for inertia in [true, false] {
+            let mut motion = Motion::new(inertia);
+            let mut pos = point("0", "0", 1.0);
+            let mut mag = 1.0;
+            motion.update(&mut pos, &mut mag, glm::vec2(1.0, 0.0), 0.0, 0.1);
+            let moved = pos.x.to_f64();
+            assert!((moved - 0.1).abs() < 1e-12);
+            // glides on after the keys are let go, then stops
+            motion.update(&mut pos, &mut mag, glm::vec2(0.0, 0.0), 0.0, 0.1);
+            assert_eq!(pos.x.to_f64() > moved, inertia);
+            for _ in 0..100 {
+                motion.update(&mut pos, &mut mag, glm::vec2(0.0, 0.0), 0.0, 0.1);
+            }
+            let stopped = pos.x.to_f64();
+            motion.update(&mut pos, &mut mag, glm::vec2(0.0, 0.0), 0.0, 0.1);
+            assert_eq!(pos.x.to_f64(), stopped);
+        }
+    }
+}
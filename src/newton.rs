@@ -0,0 +1,133 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::str::FromStr;
+
+use crate::fractal::Fractal;
+use crate::shader::Shader;
+use crate::uniform::Uniform;
+
+// has to match MAX_ROOTS in mandelbrot_frag.glsl
+pub const MAX_ROOTS: usize = 8;
+// |z - root|^2 under which the Newton method counts as converged
+const TOLERANCE: f64 = 1e-10;
+
+// the polynomial of the Newton and Nova fractals, given by its roots
+#[derive(Clone, PartialEq, Debug)]
+pub struct Polynomial {
+    pub roots: Vec<glm::DVec2>,
+    // step length of the Newton method, 1 is the plain method
+    pub relaxation: f64,
+}
+
+impl Polynomial {
+    // z^3 - 1
+    pub fn new() -> Self {
+        Self {
+            roots: (0..3)
+                .map(|i| {
+                    let angle = i as f64 * std::f64::consts::TAU / 3.0;
+                    glm::vec2(angle.cos(), angle.sin())
+                })
+                .collect(),
+            relaxation: 1.0,
+        }
+    }
+
+    pub fn add_root(&mut self, root: glm::DVec2) {
+        if self.roots.len() < MAX_ROOTS {
+            self.roots.push(root);
+        }
+    }
+
+    // a polynomial needs two roots to have anything to converge between
+    pub fn remove_root(&mut self, idx: usize) {
+        if self.roots.len() > 2 {
+            self.roots.remove(idx);
+        }
+    }
+
+    pub fn nearest_root(&self, z: glm::DVec2) -> usize {
+        self.roots.iter()
+            .map(|root| glm::distance2(root, &z))
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(idx, _)| idx)
+            .unwrap_or(0)
+    }
+
+    // the root `point` converges to, and the number of steps it took, as newton_root in the shader
+    // the point is z_0 of the Newton fractal and c of the Nova fractal, unless julia gives c
+    pub fn newton(&self, point: glm::DVec2, fractal: &Fractal, julia: Option<glm::DVec2>, max_iter: i32) -> (Option<usize>, i32) {
+        let nova = *fractal == Fractal::Nova;
+        let (mut z, c) = match (nova, julia) {
+            (false, _) => (point, glm::vec2(0.0, 0.0)),
+            (true, Some(c)) => (point, c),
+            // the roots are critical points of the Newton step, the Nova fractal starts at the first one
+            // the step is 0 there, so the first iteration only adds c
+            (true, None) => (self.roots[0] + point, point),
+        };
+        for steps in 0..max_iter {
+            // p'/p = sum 1 / (z - root)
+            let mut sum = glm::vec2(0.0, 0.0);
+            for (idx, root) in self.roots.iter().enumerate() {
+                let d = z - root;
+                let len = glm::dot(&d, &d);
+                if !nova && len < TOLERANCE {
+                    return (Some(idx), steps);
+                }
+                sum += glm::vec2(d.x, -d.y) / len;
+            }
+            let next = z - glm::vec2(sum.x, -sum.y) / glm::dot(&sum, &sum) * self.relaxation + c;
+            // the Nova fractal settles on fixed points that depend on c, not on the roots
+            if nova && glm::distance2(&next, &z) < TOLERANCE {
+                return (Some(self.nearest_root(next)), steps);
+            }
+            z = next;
+        }
+        (None, max_iter)
+    }
+
+    pub fn set_uniforms(&self, shader: &mut Shader) {
+        Uniform::new("root_count", shader).seti(self.roots.len() as i32, shader);
+        for (idx, root) in self.roots.iter().enumerate() {
+            Uniform::new(&format!("roots[{}]", idx), shader).set_2f(root.x as f32, root.y as f32, shader);
+        }
+        Uniform::new("relaxation", shader).setf(self.relaxation as f32, shader);
+    }
+}
+
+// roots as x,y pairs separated by semicolons, like 1,0;-1,0;0,1
+impl FromStr for Polynomial {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let roots = s.split(';')
+            .map(|root| {
+                let (x, y) = root.split_once(',').ok_or(format!("Invalid root, expected x,y: {}", root))?;
+                let parse = |value: &str| value.trim().parse::<f64>().map_err(|e| format!("Invalid root {}: {}", root, e));
+                Ok(glm::vec2(parse(x)?, parse(y)?))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if !(2..=MAX_ROOTS).contains(&roots.len()) {
+            return Err(format!("The polynomial needs 2 to {} roots", MAX_ROOTS));
+        }
+        Ok(Self {
+            roots,
+            relaxation: 1.0,
+        })
+    }
+}
@@ -0,0 +1,104 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use gl::types::GLint;
+
+use crate::font::{self, CELL_HEIGHT, CELL_WIDTH};
+use crate::shader::Shader;
+use crate::texture::{Texture, TextureType};
+
+const OVERLAY_UNIT: GLint = 12;
+// around the text, and between the panel and the edge of the window, in font pixels
+const PADDING: usize = 4;
+const BACKGROUND: [u8; 4] = [0, 0, 0, 200];
+const FOREGROUND: [u8; 4] = [255, 255, 255, 255];
+
+// lines of text on a dark panel in the top left corner of the window, drawn over the picture
+pub struct Overlay {
+    shader: Shader,
+    texture: Texture,
+    // size of the panel in window pixels
+    width: i32,
+    height: i32,
+    // window pixels per font pixel
+    scale: usize,
+    window_width: i32,
+    window_height: i32,
+    lines: Vec<String>,
+}
+
+impl Overlay {
+    pub fn new(vertex: &str, fragment: &str, window_width: i32, window_height: i32) -> Result<Self, String> {
+        let mut shader = Shader::new(vertex, fragment)?;
+        let texture = Texture::from_data(&[0; 4], 1, 1, OVERLAY_UNIT, TextureType::RGBA);
+        texture.link(&mut shader, "overlay");
+        Ok(Self {
+            shader,
+            texture,
+            width: 1,
+            height: 1,
+            scale: 1,
+            window_width,
+            window_height,
+            lines: Vec::new(),
+        })
+    }
+
+    // the text is drawn again for the size, it may not fit twice as big any more
+    pub fn resize(&mut self, window_width: i32, window_height: i32) {
+        self.window_width = window_width;
+        self.window_height = window_height;
+        let lines = std::mem::take(&mut self.lines);
+        self.set_text(&lines);
+    }
+
+    // the font is made twice as big when the text still fits the window
+    pub fn set_text(&mut self, lines: &[String]) {
+        self.lines = lines.to_vec();
+        let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        let width = columns * CELL_WIDTH + 2 * PADDING;
+        let height = lines.len() * CELL_HEIGHT + 2 * PADDING;
+        let fits = |scale: usize| (width + PADDING) * scale <= self.window_width as usize && (height + PADDING) * scale <= self.window_height as usize;
+        self.scale = if fits(2) { 2 } else { 1 };
+        let (width, height) = (width * self.scale, height * self.scale);
+        let mut image: Vec<u8> = BACKGROUND.iter().copied().cycle().take(width * height * 4).collect();
+        for (row, line) in lines.iter().enumerate() {
+            let y = (PADDING + row * CELL_HEIGHT) * self.scale;
+            font::draw_text(&mut image, width, PADDING * self.scale, y, self.scale, line, FOREGROUND);
+        }
+        self.texture.set_data(&image, width as u32, height as u32, TextureType::RGBA);
+        self.width = width as i32;
+        self.height = height as i32;
+    }
+
+    // blended over what is on the window
+    pub fn draw<F: FnMut()>(&mut self, mut draw: F) {
+        let margin = (PADDING * self.scale) as i32;
+        self.texture.bind();
+        self.shader.bind();
+        unsafe {
+            gl::Viewport(margin, self.window_height - margin - self.height, self.width, self.height);
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
+        draw();
+        unsafe {
+            gl::Disable(gl::BLEND);
+            gl::Viewport(0, 0, self.window_width, self.window_height);
+        }
+        self.shader.unbind();
+    }
+}
@@ -0,0 +1,326 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use gl::types::GLint;
+use std::fs;
+use std::path::Path;
+
+use crate::shader::Shader;
+use crate::texture::{InterpolationType, MapType, Texture, TextureType};
+use crate::uniform::Uniform;
+
+// texels in the lookup texture
+const TABLE_SIZE: usize = 256;
+pub const PALETTE_UNIT: GLint = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RepeatMode {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+// color gradient sampled along the (smooth) iteration count
+#[derive(Clone, Debug)]
+pub struct Palette {
+    pub name: String,
+    // the gradient interpolated into evenly spaced entries, this goes into the texture
+    table: Vec<[u8; 3]>,
+    // iterations for one pass over the gradient
+    pub scale: f32,
+    // shift along the gradient, 1.0 is a whole pass
+    pub offset: f32,
+    pub repeat: RepeatMode,
+}
+
+impl RepeatMode {
+    // has to match the PALETTE_* defines of the fragment shaders
+    pub fn id(&self) -> i32 {
+        match self {
+            RepeatMode::Repeat => 0,
+            RepeatMode::Mirror => 1,
+            RepeatMode::Clamp => 2,
+        }
+    }
+
+    // position on the gradient in [0, 1]
+    fn apply(&self, t: f32) -> f32 {
+        match self {
+            RepeatMode::Repeat => t - t.floor(),
+            RepeatMode::Mirror => 1.0 - ((t - 2.0 * (t / 2.0).floor()) - 1.0).abs(),
+            RepeatMode::Clamp => t.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl Palette {
+    // stops are (position in [0, 1], color), in any order
+    pub fn from_stops(name: &str, stops: &[(f32, [u8; 3])]) -> Result<Self, String> {
+        if stops.is_empty() {
+            return Err(format!("Palette {} has no color stops", name));
+        }
+        let mut stops = stops.to_vec();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let table = (0..TABLE_SIZE)
+            .map(|i| {
+                let t = i as f32 / (TABLE_SIZE - 1) as f32;
+                let next = stops.iter().position(|stop| stop.0 >= t).unwrap_or(stops.len() - 1);
+                let prev = next.saturating_sub(1);
+                let (a, b) = (stops[prev], stops[next]);
+                let f = if b.0 > a.0 { ((t - a.0) / (b.0 - a.0)).clamp(0.0, 1.0) } else { 1.0 };
+                let mix = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * f).round() as u8;
+                [mix(a.1[0], b.1[0]), mix(a.1[1], b.1[1]), mix(a.1[2], b.1[2])]
+            })
+            .collect();
+        Ok(Self {
+            name: name.to_owned(),
+            table,
+            scale: 32.0,
+            offset: 0.0,
+            repeat: RepeatMode::Repeat,
+        })
+    }
+
+    // .png files are gradient strips read along their first row, anything else is a palette text file
+    pub fn load(filename: &str) -> Result<Self, String> {
+        let name = Path::new(filename)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| filename.to_owned());
+        if filename.to_lowercase().ends_with(".png") {
+            Self::from_png(&name, filename)
+        } else {
+            let src = fs::read_to_string(filename).map_err(|e| format!("{}: {}", filename, e))?;
+            Self::parse(&name, &src).map_err(|e| format!("{}: {}", filename, e))
+        }
+    }
+
+    // every .txt and .png palette of a directory, sorted by file name
+    // a broken one is reported and left out, the others still load
+    pub fn load_dir(dir: &str) -> Result<Vec<Self>, String> {
+        let mut files: Vec<String> = fs::read_dir(dir)
+            .map_err(|e| format!("{}: {}", dir, e))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .filter(|path| path.extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase())
+                .is_some_and(|extension| extension == "txt" || extension == "png"))
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        Ok(files.iter()
+            .filter_map(|file| Self::load(file).map_err(|e| eprintln!("Skipping palette {}", e)).ok())
+            .collect())
+    }
+
+    fn from_png(name: &str, filename: &str) -> Result<Self, String> {
+        let (buffer, width, height) = Texture::read_png(filename)?;
+        if width == 0 || height == 0 {
+            return Err(format!("{}: empty image", filename));
+        }
+        let channels = buffer.len() / (width * height) as usize;
+        let stops: Vec<(f32, [u8; 3])> = (0..width as usize)
+            .map(|x| {
+                let pixel = &buffer[x * channels..(x + 1) * channels];
+                let t = if width > 1 { x as f32 / (width - 1) as f32 } else { 0.0 };
+                // gray and gray-alpha strips
+                if channels < 3 {
+                    (t, [pixel[0]; 3])
+                } else {
+                    (t, [pixel[0], pixel[1], pixel[2]])
+                }
+            })
+            .collect();
+        Self::from_stops(name, &stops)
+    }
+
+    // one setting or color stop per line, lines starting with # are comments:
+    //   name <name>
+    //   scale <iterations per pass>
+    //   offset <shift>
+    //   repeat repeat|mirror|clamp
+    //   <position> #rrggbb
+    fn parse(name: &str, src: &str) -> Result<Self, String> {
+        let mut name = name.to_owned();
+        let mut scale = None;
+        let mut offset = None;
+        let mut repeat = None;
+        let mut stops = Vec::new();
+        for (number, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |e: String| format!("line {}: {}", number + 1, e);
+            let (key, value) = line.split_once(char::is_whitespace)
+                .map(|(key, value)| (key, value.trim()))
+                .ok_or_else(|| error(format!("expected a setting or a color stop: {}", line)))?;
+            match key {
+                "name" => name = value.to_owned(),
+                "scale" => scale = Some(parse_number(value).map_err(error)?),
+                "offset" => offset = Some(parse_number(value).map_err(error)?),
+                "repeat" => repeat = Some(match value {
+                    "repeat" => RepeatMode::Repeat,
+                    "mirror" => RepeatMode::Mirror,
+                    "clamp" => RepeatMode::Clamp,
+                    _ => return Err(error(format!("unknown repeat mode: {}", value))),
+                }),
+                position => stops.push((parse_number(position).map_err(error)?, parse_color(value).map_err(error)?)),
+            }
+        }
+        let mut palette = Self::from_stops(&name, &stops)?;
+        palette.scale = scale.unwrap_or(palette.scale);
+        palette.offset = offset.unwrap_or(palette.offset);
+        palette.repeat = repeat.unwrap_or(palette.repeat);
+        if !(palette.scale.is_finite() && palette.scale > 0.0) {
+            return Err(format!("scale of {} has to be positive", name));
+        }
+        Ok(palette)
+    }
+
+    // the lookup texture of the shaders, on PALETTE_UNIT
+    pub fn texture(&self) -> Texture {
+        let mut texture = Texture::from_data(&self.bytes(), TABLE_SIZE as u32, 1, PALETTE_UNIT, TextureType::RGB);
+        texture.settings(InterpolationType::LINEAR, InterpolationType::LINEAR, MapType::CLAMP, MapType::CLAMP);
+        texture.bind();
+        texture
+    }
+
+    pub fn upload(&self, texture: &mut Texture) {
+        texture.set_data(&self.bytes(), TABLE_SIZE as u32, 1, TextureType::RGB);
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.table.iter().flatten().copied().collect()
+    }
+
+    // None keeps the sine coloring of the shaders
+    pub fn set_uniforms(palette: Option<&Palette>, shader: &mut Shader) {
+        Uniform::new("use_palette", shader).seti(palette.is_some() as i32, shader);
+        Uniform::new("palette", shader).seti(PALETTE_UNIT, shader);
+        if let Some(palette) = palette {
+            Uniform::new("palette_scale", shader).setf(palette.scale, shader);
+            Uniform::new("palette_offset", shader).setf(palette.offset, shader);
+            Uniform::new("palette_repeat", shader).seti(palette.repeat.id(), shader);
+        }
+    }
+
+    // the same lookup as palette_color in the shaders
+    pub fn color(&self, n: f64) -> [u8; 3] {
+        self.color_at(n as f32 / self.scale)
+    }
+
+    // t = 1.0 is one pass over the gradient, before the offset and the repeat mode
+    pub fn color_at(&self, t: f32) -> [u8; 3] {
+        let t = self.repeat.apply(t + self.offset);
+        // linear filtering between the entries, like the texture
+        let position = t * (TABLE_SIZE - 1) as f32;
+        let i = (position.floor() as usize).min(TABLE_SIZE - 2);
+        let f = position - i as f32;
+        let (a, b) = (self.table[i], self.table[i + 1]);
+        let mix = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * f).round() as u8;
+        [mix(a[0], b[0]), mix(a[1], b[1]), mix(a[2], b[2])]
+    }
+}
+
+// inf and nan parse as floats, none of the numbers of a palette can be them
+fn parse_number(value: &str) -> Result<f32, String> {
+    value.parse::<f32>().map_err(|e| format!("{}: {}", value, e))
+        .and_then(|number| match number.is_finite() {
+            true => Ok(number),
+            false => Err(format!("expected a finite number: {}", value)),
+        })
+}
+
+fn parse_color(value: &str) -> Result<[u8; 3], String> {
+    let hex = value.trim_start_matches('#');
+    let channel = |i: usize| hex.get(i..i + 2)
+        .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+        .ok_or(format!("expected a #rrggbb color: {}", value));
+    if hex.len() != 6 {
+        return Err(format!("expected a #rrggbb color: {}", value));
+    }
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_settings_and_stops() {
+        let src = "# comment\nname sunset\nscale 48\noffset 0.25\nrepeat clamp\n1.0 #ffffff\n0.0 #000000\n0.5 #ff0000\n";
+        let palette = Palette::parse("file", src).unwrap();
+        assert_eq!(palette.name, "sunset");
+        assert_eq!((palette.scale, palette.offset, palette.repeat), (48.0, 0.25, RepeatMode::Clamp));
+        // the stops are sorted, and the offset moves along the gradient
+        assert_eq!(palette.color_at(-0.25), [0, 0, 0]);
+        // the middle stop falls between two entries of the table
+        let [r, g, b] = palette.color_at(0.25);
+        assert!(r == 255 && g <= 1 && b <= 1, "{:?}", [r, g, b]);
+        assert_eq!(palette.color_at(0.75), [255, 255, 255]);
+        let plain = Palette::parse("plain", "0 #102030").unwrap();
+        assert_eq!((plain.name.as_str(), plain.scale, plain.offset, plain.repeat), ("plain", 32.0, 0.0, RepeatMode::Repeat));
+    }
+
+    #[test]
+    fn parse_errors() {
+        for src in [
+            "",
+            "# only a comment",
+            "scale 0\n0 #000000",
+            "scale -3\n0 #000000",
+            "scale nan\n0 #000000",
+            "offset inf\n0 #000000",
+            "nan #000000",
+            "0 #00000",
+            "0 #gg0000",
+            "repeat sometimes\n0 #000000",
+            "0.5",
+        ] {
+            assert!(Palette::parse("broken", src).is_err(), "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn repeat_modes() {
+        for (t, repeat, mirror, clamp) in [
+            (0.0, 0.0, 0.0, 0.0),
+            (0.25, 0.25, 0.25, 0.25),
+            (1.0, 0.0, 1.0, 1.0),
+            (1.25, 0.25, 0.75, 1.0),
+            (2.0, 0.0, 0.0, 1.0),
+            (-0.25, 0.75, 0.25, 0.0),
+            (-1.0, 0.0, 1.0, 0.0),
+        ] {
+            assert_eq!(RepeatMode::Repeat.apply(t), repeat, "repeat {}", t);
+            assert_eq!(RepeatMode::Mirror.apply(t), mirror, "mirror {}", t);
+            assert_eq!(RepeatMode::Clamp.apply(t), clamp, "clamp {}", t);
+        }
+    }
+
+    #[test]
+    fn gradient_ends() {
+        let mut palette = Palette::from_stops("ends", &[(0.0, [0, 0, 0]), (1.0, [200, 100, 50])]).unwrap();
+        palette.repeat = RepeatMode::Clamp;
+        assert_eq!(palette.color_at(0.0), [0, 0, 0]);
+        assert_eq!(palette.color_at(1.0), [200, 100, 50]);
+        assert_eq!(palette.color_at(5.0), [200, 100, 50]);
+        assert_eq!(palette.color(palette.scale as f64 * 0.5), [100, 50, 25]);
+        assert!(Palette::from_stops("empty", &[]).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use gl::types::{GLint, GLsizei};
+
+use crate::bigfloat::{BigFloat, BigVec2};
+use crate::coloring::Coloring;
+use crate::palette::Palette;
+use crate::sampling::Supersampling;
+use crate::shader::Shader;
+use crate::texture::{Texture, TextureType};
+use crate::uniform::Uniform;
+
+// has to match ORBIT_WIDTH in perturbation_frag.glsl
+const ORBIT_WIDTH: usize = 1024;
+const ORBIT_UNIT: GLint = 0;
+// references per frame, including the primary one at the center of the view
+const MAX_REFERENCES: usize = 8;
+
+struct Reference {
+    // view center minus the reference point, in units of 2^delta_exponent
+    offset: glm::DVec2,
+    // Z_n of the reference point as interleaved x, y pairs
+    orbit: Vec<f32>,
+}
+
+type ViewKey = (BigVec2, glm::DMat3, i32, Option<BigVec2>, f64);
+
+// deep zoom beyond double precision: one big float reference orbit per region,
+// and the GPU iterates the small difference of every pixel from it
+pub struct DeepRenderer {
+    shader: Shader,
+    orbit: Texture,
+    references: Vec<Reference>,
+    // the view the references were computed for
+    view: Option<ViewKey>,
+    // window size in pixels, for reading back the stencil buffer
+    width: i32,
+    height: i32,
+}
+
+impl Reference {
+    // the Julia set of c starts from the point itself, the Mandelbrot set from zero with c = point
+    fn new(point: &BigVec2, offset: glm::DVec2, max_iter: i32, julia: Option<&BigVec2>, escape_radius: f64) -> Self {
+        let limbs = point.x.limbs();
+        let (mut x, mut y, c) = match julia {
+            Some(c) => (point.x.clone(), point.y.clone(), c),
+            None => (BigFloat::zero(limbs), BigFloat::zero(limbs), point),
+        };
+        let mut orbit = vec![x.to_f64() as f32, y.to_f64() as f32];
+        for _ in 0..max_iter {
+            let x_2 = &x * &x;
+            let y_2 = &y * &y;
+            y = &(&x * &y).mul_pow2(1) + &c.y;
+            x = &(&x_2 - &y_2) + &c.x;
+            // escape is tested on the floats, the same way the shader does
+            let (x_f, y_f) = (x.to_f64() as f32, y.to_f64() as f32);
+            orbit.push(x_f);
+            orbit.push(y_f);
+            if x_f * x_f + y_f * y_f > (escape_radius * escape_radius) as f32 {
+                break;
+            }
+        }
+        Self { offset, orbit }
+    }
+
+    fn length(&self) -> usize {
+        self.orbit.len() / 2
+    }
+
+    // the orbit wrapped into rows of ORBIT_WIDTH texels
+    fn upload(&self, texture: &mut Texture) {
+        let rows = self.length().div_ceil(ORBIT_WIDTH);
+        let mut data = self.orbit.clone();
+        data.resize(rows * ORBIT_WIDTH * 2, 0.0);
+        let bytes: Vec<u8> = data.iter().flat_map(|value| value.to_ne_bytes()).collect();
+        texture.set_data(&bytes, ORBIT_WIDTH as u32, rows as u32, TextureType::RG32F);
+    }
+}
+
+impl DeepRenderer {
+    pub fn new(vertex: &str, fragment: &str, width: i32, height: i32) -> Result<Self, String> {
+        let mut shader = Shader::new(vertex, fragment)?;
+        let orbit = Texture::from_data(&[0; 8], 1, 1, ORBIT_UNIT, TextureType::RG32F);
+        orbit.link(&mut shader, "orbit");
+        Ok(Self {
+            shader,
+            orbit,
+            references: Vec::new(),
+            view: None,
+            width,
+            height,
+        })
+    }
+
+    // the references are computed again for the new view
+    pub fn resize(&mut self, width: i32, height: i32) {
+        self.width = width;
+        self.height = height;
+        self.view = None;
+    }
+
+    // gradient of the coloring, None for the sine colors
+    pub fn set_palette(&mut self, palette: Option<&Palette>) {
+        Palette::set_uniforms(palette, &mut self.shader);
+    }
+
+    pub fn set_supersampling(&mut self, supersampling: &Supersampling) {
+        supersampling.set_uniforms(&mut self.shader);
+    }
+
+    // `center` is the middle of the view in the complex plane, mvp maps the view onto it
+    // the stencil buffer keeps track of the pixels that are already correct
+    pub fn draw<F: FnMut()>(&mut self, center: &BigVec2, mvp: &glm::DMat3, max_iter: i32, julia: Option<&BigVec2>, coloring: &Coloring, mut draw: F) {
+        // deltas are measured in 2^delta_exponent, about the size of the view
+        let delta_exponent = mvp[(1, 1)].abs().log2().floor() as i32;
+        let unit = 2f64.powi(delta_exponent);
+        let linear = glm::mat2(
+            mvp[(0, 0)], mvp[(0, 1)],
+            mvp[(1, 0)], mvp[(1, 1)],
+        );
+        let scale = linear / unit;
+
+        let radius = coloring.escape_radius;
+        let view = (center.clone(), *mvp, max_iter, julia.cloned(), radius);
+        let fresh = self.view.as_ref() != Some(&view);
+        if fresh {
+            self.references.clear();
+            self.references.push(Reference::new(center, glm::vec2(0.0, 0.0), max_iter, julia, radius));
+            self.view = Some(view);
+        }
+
+        unsafe {
+            gl::ClearStencil(0);
+            gl::Clear(gl::STENCIL_BUFFER_BIT);
+            gl::Enable(gl::STENCIL_TEST);
+            gl::StencilOp(gl::KEEP, gl::KEEP, gl::REPLACE);
+        }
+        self.shader.bind();
+        Uniform::new("max_iter", &mut self.shader).seti(max_iter, &mut self.shader);
+        Uniform::new("julia", &mut self.shader).seti(julia.is_some() as i32, &mut self.shader);
+        coloring.set_uniforms(&mut self.shader);
+        Uniform::new("delta_exponent", &mut self.shader).seti(delta_exponent, &mut self.shader);
+        Uniform::new("scale", &mut self.shader).set_m2f(&scale.map(|x| x as f32), &mut self.shader);
+
+        let mut pass = 0;
+        loop {
+            if pass == self.references.len() {
+                // only a new view gets new references, the old ones are reused otherwise
+                if !fresh || pass == MAX_REFERENCES {
+                    break;
+                }
+                let tex = match glitched_pixel(self.width, self.height) {
+                    Some(tex) => tex,
+                    None => break,
+                };
+                let delta = linear * tex;
+                self.references.push(Reference::new(&center.offset(&delta), -delta / unit, max_iter, julia, radius));
+            }
+            let reference = &self.references[pass];
+            reference.upload(&mut self.orbit);
+            Uniform::new("orbit_length", &mut self.shader).seti(reference.length() as GLint, &mut self.shader);
+            let offset = reference.offset;
+            Uniform::new("offset", &mut self.shader).set_2f(offset.x as f32, offset.y as f32, &mut self.shader);
+            unsafe {
+                // drawn pixels are marked with 1, passes after the first one only touch the rest
+                gl::StencilFunc(if pass == 0 { gl::ALWAYS } else { gl::NOTEQUAL }, 1, 0xff);
+            }
+            draw();
+            pass += 1;
+        }
+
+        unsafe {
+            gl::Disable(gl::STENCIL_TEST);
+        }
+        self.shader.unbind();
+    }
+}
+
+// the glitched pixel closest to the centroid of all glitched pixels, in tex_coord space
+fn glitched_pixel(width: i32, height: i32) -> Option<glm::DVec2> {
+    let mut stencil = vec![0u8; (width * height) as usize];
+    unsafe {
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(0, 0, width as GLsizei, height as GLsizei, gl::STENCIL_INDEX, gl::UNSIGNED_BYTE, stencil.as_mut_ptr() as *mut _);
+    }
+    let glitched: Vec<(i32, i32)> = stencil.iter()
+        .enumerate()
+        .filter(|(_, value)| **value == 0)
+        .map(|(i, _)| (i as i32 % width, i as i32 / width))
+        .collect();
+    if glitched.is_empty() {
+        return None;
+    }
+    let count = glitched.len() as f64;
+    let centroid_x = glitched.iter().map(|p| p.0 as f64).sum::<f64>() / count;
+    let centroid_y = glitched.iter().map(|p| p.1 as f64).sum::<f64>() / count;
+    let (x, y) = glitched.iter()
+        .min_by(|a, b| {
+            let da = (a.0 as f64 - centroid_x).powi(2) + (a.1 as f64 - centroid_y).powi(2);
+            let db = (b.0 as f64 - centroid_x).powi(2) + (b.1 as f64 - centroid_y).powi(2);
+            da.total_cmp(&db)
+        })
+        .copied()?;
+    Some(glm::vec2(
+        (x as f64 + 0.5) / width as f64 * 2.0 - 1.0,
+        (y as f64 + 0.5) / height as f64 * 2.0 - 1.0))
+}
@@ -0,0 +1,163 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::bigfloat::BigVec2;
+use crate::shader::Shader;
+use crate::uniform::Uniform;
+
+// relative precision of the double-float emulation, two floats give about 48 bits instead of 53,
+// with the same few bits of room for the rounding errors as the other shaders
+const EMULATED_EPSILON: f64 = 1.0 / (1u64 << 44) as f64;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Precision {
+    Single,
+    Double,
+    Emulated,
+    // drawn by perturbation::DeepRenderer instead of these shaders
+    Perturbation,
+}
+
+// one compiled fragment shader for every precision the context can run
+pub struct PrecisionShaders {
+    single: Shader,
+    double: Option<Shader>,
+    emulated: Shader,
+}
+
+impl Precision {
+    // the cheapest precision that still resolves neighbouring pixels of the view
+    // without native doubles the emulated ones give up earlier
+    pub fn required(mvp: &glm::DMat3, height: f64, native_double: bool) -> Self {
+        let spacing = 2.0 * mvp[(1, 1)].abs() / height;
+        let magnitude = mvp[(0, 2)].abs().max(mvp[(1, 2)].abs())
+            + mvp[(0, 0)].abs().max(mvp[(1, 1)].abs());
+        // leave a few bits for the rounding errors piling up during the iteration
+        if spacing > magnitude * f32::EPSILON as f64 * 16.0 {
+            Precision::Single
+        } else if spacing > magnitude * if native_double { f64::EPSILON * 16.0 } else { EMULATED_EPSILON } {
+            Precision::Double
+        } else {
+            Precision::Perturbation
+        }
+    }
+
+    pub fn set_view(&self, mvp: &glm::DMat3, shader: &mut Shader) {
+        match self {
+            Precision::Single => {
+                Uniform::new("mvp", shader).set_m3f(&mvp.map(|x| x as f32), shader);
+            },
+            Precision::Double => {
+                Uniform::new("mvp", shader).set_m3d(mvp, shader);
+            },
+            Precision::Emulated => {
+                let (x_hi, x_lo) = split(mvp[(0, 2)]);
+                let (y_hi, y_lo) = split(mvp[(1, 2)]);
+                Uniform::new("center", shader).set_4f(x_hi, x_lo, y_hi, y_lo, shader);
+                let scale = glm::mat2(
+                    mvp[(0, 0)] as f32, mvp[(0, 1)] as f32,
+                    mvp[(1, 0)] as f32, mvp[(1, 1)] as f32,
+                );
+                Uniform::new("scale", shader).set_m2f(&scale, shader);
+            },
+            Precision::Perturbation => {},
+        }
+    }
+
+    // None draws the Mandelbrot set, Some(c) the Julia set of c
+    pub fn set_julia(&self, julia: Option<&BigVec2>, shader: &mut Shader) {
+        Uniform::new("julia", shader).seti(julia.is_some() as i32, shader);
+        let c = match julia {
+            Some(c) => c,
+            None => return,
+        };
+        match self {
+            Precision::Single => {
+                Uniform::new("c", shader).set_2f(c.x.to_f64() as f32, c.y.to_f64() as f32, shader);
+            },
+            Precision::Double => {
+                Uniform::new("c", shader).set_2d(c.x.to_f64(), c.y.to_f64(), shader);
+            },
+            Precision::Emulated => {
+                let (x_hi, x_lo) = split(c.x.to_f64());
+                let (y_hi, y_lo) = split(c.y.to_f64());
+                Uniform::new("c", shader).set_4f(x_hi, x_lo, y_hi, y_lo, shader);
+            },
+            Precision::Perturbation => {},
+        }
+    }
+}
+
+impl PrecisionShaders {
+    pub fn new(vertex: &str, fragment: &str) -> Result<Self, String> {
+        let mut shaders = Self {
+            single: Shader::with_defines(vertex, fragment, &[])?,
+            // fails to compile when the context has no GL_ARB_gpu_shader_fp64
+            double: Shader::with_defines(vertex, fragment, &["DOUBLE_PRECISION"]).ok(),
+            emulated: Shader::with_defines(vertex, fragment, &["EMULATED_DOUBLE"])?,
+        };
+        shaders.unbind();
+        Ok(shaders)
+    }
+
+    pub fn unbind(&mut self) {
+        self.single.unbind();
+        if let Some(double) = self.double.as_mut() {
+            double.unbind();
+        }
+        self.emulated.unbind();
+    }
+
+    // the context has GL_ARB_gpu_shader_fp64
+    pub fn native_double(&self) -> bool {
+        self.double.is_some()
+    }
+
+    // falls back to the emulated double-float shader without native doubles
+    pub fn available(&self, precision: Precision) -> Precision {
+        match (precision, &self.double) {
+            (Precision::Double, None) => Precision::Emulated,
+            _ => precision,
+        }
+    }
+
+    // the other shaders are unbound, so that their bound flags don't go stale
+    pub fn get(&mut self, precision: Precision) -> &mut Shader {
+        let precision = self.available(precision);
+        if precision != Precision::Single {
+            self.single.unbind();
+        }
+        if precision != Precision::Emulated {
+            self.emulated.unbind();
+        }
+        if precision != Precision::Double {
+            if let Some(double) = self.double.as_mut() {
+                double.unbind();
+            }
+        }
+        match (precision, self.double.as_mut()) {
+            (Precision::Double, Some(double)) => double,
+            (Precision::Single, _) => &mut self.single,
+            _ => &mut self.emulated,
+        }
+    }
+}
+
+// an f64 as the sum of two f32, for the double-float shader
+fn split(value: f64) -> (f32, f32) {
+    let hi = value as f32;
+    (hi, (value - hi as f64) as f32)
+}
@@ -0,0 +1,340 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use gl::types::GLint;
+use std::time::{Duration, Instant};
+
+use crate::buffer::FrameBuffer;
+use crate::shader::Shader;
+use crate::texture::{Texture, TextureType};
+use crate::uniform::Uniform;
+
+const TARGET_UNIT: GLint = 6;
+// the z and the orbit state of the two framebuffers
+const STATE_UNITS: [[GLint; 2]; 2] = [[7, 8], [9, 10]];
+const SCRATCH_UNIT: GLint = 11;
+// iteration limit of the first pass over a new picture, the passes after it double it up to max_iter
+const FIRST_PASS: i32 = 256;
+// the orbit state keeps the iteration count in a float, exact up to 2^24, a pass beyond it starts from the beginning
+const MAX_RESUME: i32 = 1 << 24;
+// drawing time a frame may spend on tiles, the events are handled between the frames
+const BUDGET: Duration = Duration::from_millis(12);
+// tiles are squares of a power of two size between these, cut off at the edges of the window
+const MIN_TILE: i32 = 16;
+const MAX_TILE: i32 = 2048;
+// the orbit state of the pixels that have nothing to continue
+const NO_ORBIT: [f32; 4] = [-1.0, 0.0, 0.0, 0.0];
+
+// part of the window, from its bottom left corner
+#[derive(Clone, Copy, Debug)]
+struct Tile {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+// the frame is split into tiles drawn over several frames, into an offscreen target that keeps
+// them until the view changes, so expensive frames never hold up the window or the GPU for long
+// K is everything the picture depends on but the view and the iteration limit, a different one starts the tiles over
+// besides the colors, every pixel saves where its orbit got to, and a pass with a higher limit
+// continues from there, ping-ponging the state between the two framebuffers
+pub struct Progressive<K: PartialEq> {
+    framebuffers: [FrameBuffer; 2],
+    _target: Texture,
+    // z and the rest of the orbit of every pixel, for each framebuffer
+    states: [[Texture; 2]; 2],
+    // a copy of the window for scrolling the others
+    scratch: FrameBuffer,
+    _scratch_texture: Texture,
+    width: i32,
+    height: i32,
+    key: Option<K>,
+    // the view the target was drawn with
+    view: Option<glm::DMat3>,
+    // the framebuffer the current pass draws into, the state of the previous one is in the other
+    write: usize,
+    // iteration limit of the current pass
+    pass_iter: i32,
+    // if the current pass goes on from the orbits of the previous one
+    resume: bool,
+    // the tiles of the current picture, the ones close to the middle first
+    tiles: Vec<Tile>,
+    next: usize,
+    // measured drawing time of a pixel, it sets the size of the tiles
+    pixel_cost: Duration,
+    // if the orbits of the finished pass were handed out already
+    measured: bool,
+}
+
+impl<K: PartialEq> Progressive<K> {
+    pub fn new(width: i32, height: i32) -> Result<Self, String> {
+        let target = Texture::from_data(&vec![0; (width * height * 4) as usize], width as u32, height as u32, TARGET_UNIT, TextureType::RGBA);
+        let empty = vec![0; (width * height * 16) as usize];
+        let states = STATE_UNITS.map(|units| units.map(|unit| Texture::from_data(&empty, width as u32, height as u32, unit, TextureType::RGBA32F)));
+        let framebuffers = [
+            FrameBuffer::with_attachments(&[&target, &states[0][0], &states[0][1]], width, height)?,
+            FrameBuffer::with_attachments(&[&target, &states[1][0], &states[1][1]], width, height)?,
+        ];
+        let scratch_texture = Texture::from_data(&empty, width as u32, height as u32, SCRATCH_UNIT, TextureType::RGBA32F);
+        let scratch = FrameBuffer::new(&scratch_texture, width, height)?;
+        Ok(Self {
+            framebuffers,
+            _target: target,
+            states,
+            scratch,
+            _scratch_texture: scratch_texture,
+            width,
+            height,
+            key: None,
+            view: None,
+            write: 0,
+            pass_iter: 0,
+            resume: false,
+            tiles: Vec::new(),
+            next: 0,
+            pixel_cost: Duration::ZERO,
+            measured: false,
+        })
+    }
+
+    // new targets for the new size of the window, the picture starts over
+    pub fn resize(&mut self, width: i32, height: i32) -> Result<(), String> {
+        *self = Self::new(width, height)?;
+        Ok(())
+    }
+
+    // picks the pass to draw, before the uniforms are set, and returns the view to draw it with
+    // resumable tells if the shader saves the orbits, it doesn't for several samples per pixel or the histogram
+    // scrollable tells if the picture is in the target, so that a move of the view only draws what came into view
+    pub fn update(&mut self, key: K, mvp: &glm::DMat3, max_iter: i32, resumable: bool, scrollable: bool) -> glm::DMat3 {
+        if self.key.as_ref() != Some(&key) || max_iter < self.pass_iter || (!resumable && max_iter != self.pass_iter) {
+            return self.start_over(key, mvp, max_iter, resumable);
+        }
+        let view = match self.view {
+            Some(view) if view != *mvp => match scrollable {
+                true => self.scroll(&view, mvp),
+                false => None,
+            },
+            _ => Some(*mvp),
+        };
+        let view = match view {
+            Some(view) => view,
+            None => return self.start_over(key, mvp, max_iter, resumable),
+        };
+        if self.done() && self.pass_iter < max_iter {
+            // only the pixels that didn't escape yet have anything left to do
+            let pass_iter = self.pass_iter.saturating_mul(2).min(max_iter);
+            self.write = 1 - self.write;
+            self.resume = pass_iter <= MAX_RESUME;
+            self.pass_iter = if self.resume { pass_iter } else { max_iter };
+            self.tiles = self.cover(0, 0, self.width, self.height);
+            self.next = 0;
+            self.measured = false;
+        }
+        view
+    }
+
+    fn start_over(&mut self, key: K, mvp: &glm::DMat3, max_iter: i32, resumable: bool) -> glm::DMat3 {
+        self.key = Some(key);
+        self.view = Some(*mvp);
+        self.resume = false;
+        self.pass_iter = if resumable { max_iter.min(FIRST_PASS) } else { max_iter };
+        self.tiles = self.cover(0, 0, self.width, self.height);
+        self.next = 0;
+        self.measured = false;
+        *mvp
+    }
+
+    // moves the picture along with the view when it moved by whole pixels, returns the view that is on the pixel grid
+    // of the target, it is less than half a pixel away from mvp, None if nothing can be kept
+    fn scroll(&mut self, view: &glm::DMat3, mvp: &glm::DMat3) -> Option<glm::DMat3> {
+        let linear = glm::mat2(
+            view[(0, 0)], view[(0, 1)],
+            view[(1, 0)], view[(1, 1)],
+        );
+        let same_zoom = (0..2).all(|row| (0..2).all(|col| view[(row, col)] == mvp[(row, col)]));
+        if !same_zoom {
+            return None;
+        }
+        // how far the new view is from the old one, in pixels
+        let moved = glm::inverse(&linear) * glm::vec2(mvp[(0, 2)] - view[(0, 2)], mvp[(1, 2)] - view[(1, 2)]);
+        let (dx, dy) = ((moved.x * self.width as f64 / 2.0).round() as i32, (moved.y * self.height as f64 / 2.0).round() as i32);
+        if dx.abs() >= self.width || dy.abs() >= self.height {
+            return None;
+        }
+        let mut snapped = *view;
+        let offset = linear * glm::vec2(dx as f64 * 2.0 / self.width as f64, dy as f64 * 2.0 / self.height as f64);
+        snapped[(0, 2)] += offset.x;
+        snapped[(1, 2)] += offset.y;
+        if dx == 0 && dy == 0 {
+            return Some(snapped);
+        }
+        // the picture moves the other way than the view, the target is attached to both framebuffers
+        self.framebuffers[0].shift(0, &mut self.scratch, -dx, -dy);
+        for framebuffer in self.framebuffers.iter_mut() {
+            for attachment in 1..3 {
+                framebuffer.shift(attachment, &mut self.scratch, -dx, -dy);
+            }
+        }
+        // the strips that came into view
+        let columns = if dx > 0 { (self.width - dx, dx) } else { (0, -dx) };
+        let rows = if dy > 0 { (self.height - dy, dy) } else { (0, -dy) };
+        let mut exposed = Vec::new();
+        if columns.1 > 0 {
+            exposed.push(Tile { x: columns.0, y: 0, width: columns.1, height: self.height });
+        }
+        if rows.1 > 0 {
+            exposed.push(Tile { x: 0, y: rows.0, width: self.width, height: rows.1 });
+        }
+        for strip in exposed.iter() {
+            self.framebuffers[0].clear(0, strip.x, strip.y, strip.width, strip.height, [0.0, 0.0, 0.0, 1.0]);
+            for framebuffer in self.framebuffers.iter_mut() {
+                framebuffer.clear(2, strip.x, strip.y, strip.width, strip.height, NO_ORBIT);
+                framebuffer.unbind();
+            }
+        }
+        // the tiles still to draw move with the picture, and the strips are drawn with them
+        let (width, height) = (self.width, self.height);
+        let mut tiles: Vec<Tile> = self.tiles[self.next..].iter()
+            .filter_map(|tile| tile.moved(-dx, -dy, width, height))
+            .collect();
+        for strip in exposed.iter() {
+            tiles.extend(self.cover(strip.x, strip.y, strip.width, strip.height));
+        }
+        self.tiles = tiles;
+        self.next = 0;
+        self.measured = false;
+        self.view = Some(snapped);
+        Some(snapped)
+    }
+
+    // the iteration limit of the pass, and where the orbits of the previous one are
+    pub fn set_uniforms(&self, shader: &mut Shader) {
+        let read = &self.states[1 - self.write];
+        Uniform::new("max_iter", shader).seti(self.pass_iter, shader);
+        Uniform::new("resume", shader).seti(self.resume as i32, shader);
+        Uniform::new("state_z", shader).seti(read[0].unit(), shader);
+        Uniform::new("state_orbit", shader).seti(read[1].unit(), shader);
+    }
+
+    // the tiles go into the offscreen target after this
+    pub fn begin(&mut self) {
+        self.framebuffers[self.write].bind();
+    }
+
+    // copies the target onto the window
+    pub fn finish(&mut self) {
+        self.framebuffers[self.write].blit();
+    }
+
+    pub fn done(&self) -> bool {
+        self.next >= self.tiles.len()
+    }
+
+    // the iteration limit and the orbit state of every pixel, once after a pass is finished
+    pub fn finished(&mut self) -> Option<(i32, Vec<f32>)> {
+        if !self.done() || self.measured {
+            return None;
+        }
+        self.measured = true;
+        let framebuffer = &mut self.framebuffers[self.write];
+        let orbits = framebuffer.read_rgba(2);
+        framebuffer.unbind();
+        Some((self.pass_iter, orbits))
+    }
+
+    // draws the next tiles into the bound framebuffer until the time of the frame is spent
+    // `draw` draws the whole screen, the scissor test cuts it down to a tile
+    pub fn draw<F: FnMut()>(&mut self, mut draw: F) {
+        let start = Instant::now();
+        unsafe {
+            gl::Enable(gl::SCISSOR_TEST);
+        }
+        while !self.done() && start.elapsed() < BUDGET {
+            let tile = self.tiles[self.next];
+            let tile_start = Instant::now();
+            unsafe {
+                gl::Scissor(tile.x, tile.y, tile.width, tile.height);
+            }
+            draw();
+            // waits for the tile, so that its time is known and the GPU never gets a long batch
+            unsafe {
+                gl::Finish();
+            }
+            let elapsed = tile_start.elapsed();
+            self.pixel_cost = elapsed / (tile.width * tile.height).max(1) as u32;
+            self.next += 1;
+            // the picture costs more than the last one did, the tiles left are split up
+            if elapsed > BUDGET && tile.width.max(tile.height) > MIN_TILE {
+                let rest = self.tiles.split_off(self.next);
+                self.tiles.extend(rest.iter().flat_map(|tile| tile.split()));
+            }
+        }
+        unsafe {
+            gl::Disable(gl::SCISSOR_TEST);
+        }
+    }
+
+    // tiles over a part of the window, as large as still fits in the time of a frame at the last measured cost
+    fn cover(&self, x: i32, y: i32, width: i32, height: i32) -> Vec<Tile> {
+        let budget_pixels = match self.pixel_cost.as_nanos() {
+            0 => u128::MAX,
+            cost => BUDGET.as_nanos() / cost,
+        };
+        let mut size = MAX_TILE;
+        while size > MIN_TILE && (size as u128 * size as u128) > budget_pixels {
+            size /= 2;
+        }
+        let center = glm::vec2(self.width as f64, self.height as f64) / 2.0;
+        let distance = |tile: &Tile| {
+            let middle = glm::vec2(tile.x as f64 + tile.width as f64 / 2.0, tile.y as f64 + tile.height as f64 / 2.0);
+            glm::distance2(&middle, &center)
+        };
+        let mut tiles: Vec<Tile> = (y..y + height).step_by(size as usize)
+            .flat_map(|tile_y| (x..x + width).step_by(size as usize).map(move |tile_x| Tile {
+                x: tile_x,
+                y: tile_y,
+                width: size.min(x + width - tile_x),
+                height: size.min(y + height - tile_y),
+            }))
+            .collect();
+        tiles.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+        tiles
+    }
+}
+
+impl Tile {
+    // the quarters, the ones that are not empty
+    fn split(&self) -> Vec<Tile> {
+        let (left, bottom) = (self.width / 2, self.height / 2);
+        [
+            Tile { x: self.x, y: self.y, width: left, height: bottom },
+            Tile { x: self.x + left, y: self.y, width: self.width - left, height: bottom },
+            Tile { x: self.x, y: self.y + bottom, width: left, height: self.height - bottom },
+            Tile { x: self.x + left, y: self.y + bottom, width: self.width - left, height: self.height - bottom },
+        ].into_iter()
+            .filter(|tile| tile.width > 0 && tile.height > 0)
+            .collect()
+    }
+
+    // moved by (dx, dy) and cut to the window, None when nothing of it is left on it
+    fn moved(&self, dx: i32, dy: i32, width: i32, height: i32) -> Option<Tile> {
+        let (x0, y0) = ((self.x + dx).max(0), (self.y + dy).max(0));
+        let (x1, y1) = ((self.x + dx + self.width).min(width), (self.y + dy + self.height).min(height));
+        (x1 > x0 && y1 > y0).then_some(Tile { x: x0, y: y0, width: x1 - x0, height: y1 - y0 })
+    }
+}
@@ -0,0 +1,310 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::fs::File;
+use std::io::BufWriter;
+
+use crate::coloring::{self, ColorMode, Coloring, Escape};
+use crate::fractal::Fractal;
+use crate::histogram::Histogram;
+use crate::newton::Polynomial;
+use crate::palette::Palette;
+use crate::sampling::{self, Supersampling, SamplePattern};
+use crate::trap::{TrapImage, TrapShape};
+
+// CPU implementation of the fragment shader, used for rendering without a window or GPU
+pub struct Renderer {
+    width: u32,
+    height: u32,
+    mvp: glm::DMat3,
+    max_iter: i32,
+    fractal: Fractal,
+    julia: Option<glm::DVec2>,
+    coloring: Coloring,
+    palette: Option<Palette>,
+    trap_image: Option<TrapImage>,
+    polynomial: Polynomial,
+    supersampling: Supersampling,
+}
+
+impl Renderer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            mvp: glm::mat3(
+                1.0, 0.0, 0.0,
+                0.0, 1.0, 0.0,
+                0.0, 0.0, 1.0,
+            ),
+            max_iter: 2,
+            fractal: Fractal::Mandelbrot,
+            julia: None,
+            coloring: Coloring::new(ColorMode::Banded),
+            palette: None,
+            trap_image: None,
+            polynomial: Polynomial::new(),
+            supersampling: Supersampling::new(SamplePattern::Grid, 1),
+        }
+    }
+
+    pub fn size(&mut self, width: u32, height: u32) -> &mut Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn mvp(&mut self, mvp: &glm::DMat3) -> &mut Self {
+        self.mvp = *mvp;
+        self
+    }
+
+    pub fn max_iter(&mut self, max_iter: i32) -> &mut Self {
+        self.max_iter = max_iter;
+        self
+    }
+
+    pub fn fractal(&mut self, fractal: Fractal) -> &mut Self {
+        self.fractal = fractal;
+        self
+    }
+
+    pub fn coloring(&mut self, coloring: Coloring) -> &mut Self {
+        self.coloring = coloring;
+        self
+    }
+
+    // None keeps the sine colors
+    pub fn palette(&mut self, palette: Option<Palette>) -> &mut Self {
+        self.palette = palette;
+        self
+    }
+
+    // the picture of the image trap
+    pub fn trap_image(&mut self, image: Option<TrapImage>) -> &mut Self {
+        self.trap_image = image;
+        self
+    }
+
+    // roots of the Newton and Nova fractals
+    pub fn polynomial(&mut self, polynomial: Polynomial) -> &mut Self {
+        self.polynomial = polynomial;
+        self
+    }
+
+    // samples per pixel, the histogram mode always takes one
+    pub fn supersampling(&mut self, supersampling: Supersampling) -> &mut Self {
+        self.supersampling = supersampling;
+        self
+    }
+
+    // Some(c) renders the Julia set of c
+    pub fn julia(&mut self, julia: Option<glm::DVec2>) -> &mut Self {
+        self.julia = julia;
+        self
+    }
+
+    // RGB bytes, rows top to bottom
+    pub fn render(&self) -> Vec<u8> {
+        // one row of pixels in the complex plane
+        let pixel = glm::length(&self.mvp.column(1).xy()) * 2.0 / self.height as f64;
+        if self.coloring.mode == ColorMode::Histogram && !self.fractal.newton() {
+            return self.render_histogram(pixel);
+        }
+        let mut pixels = Vec::with_capacity((self.width * self.height * 3) as usize);
+        for row in 0..self.height {
+            for col in 0..self.width {
+                // the sample offsets are in framebuffer pixels, rows bottom to top
+                let samples: Vec<[u8; 3]> = self.supersampling.offsets(col, self.height - 1 - row).iter()
+                    .map(|offset| self.sample(col as f64 + 0.5 + offset.x, row as f64 + 0.5 - offset.y, pixel))
+                    .collect();
+                pixels.extend(sampling::average(&samples));
+            }
+        }
+        pixels
+    }
+
+    // same tex_coord the rasterizer hands to the fragment shader, for a point of the window (y down)
+    fn tex_coord(&self, x: f64, y: f64) -> glm::DVec3 {
+        glm::vec3(
+            x / self.width as f64 * 2.0 - 1.0,
+            1.0 - y / self.height as f64 * 2.0,
+            1.0)
+    }
+
+    // the color at a point of the window, one sample of a pixel, as sample_color in the shader
+    fn sample(&self, x: f64, y: f64, pixel: f64) -> [u8; 3] {
+        let corrected = self.mvp * self.tex_coord(x, y);
+        if self.fractal.newton() {
+            // colored by the roots instead of the escape time, none of the coloring modes apply
+            let (root, steps) = self.polynomial.newton(corrected.xy(), &self.fractal, self.julia, self.max_iter);
+            return coloring::root_color(root, steps, self.polynomial.roots.len(), self.palette.as_ref());
+        }
+        let image = self.trap_image.as_ref();
+        let escape = calc_pixel(&corrected, &self.fractal, self.julia, self.max_iter, &self.coloring, pixel / 1024.0, image);
+        self.coloring.color(&escape, pixel, self.max_iter, self.fractal.power(), self.palette.as_ref())
+    }
+
+    // the raw counts can't be averaged, one sample in the middle of every pixel
+    fn render_histogram(&self, pixel: f64) -> Vec<u8> {
+        let power = self.fractal.power();
+        let mut counts = Vec::with_capacity((self.width * self.height) as usize);
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let corrected = self.mvp * self.tex_coord(col as f64 + 0.5, row as f64 + 0.5);
+                let escape = calc_pixel(&corrected, &self.fractal, self.julia, self.max_iter, &self.coloring, pixel / 1024.0, None);
+                // the pixels that never escaped are -1, like in the iteration texture of the GPU
+                counts.push(match escape.iters < self.max_iter {
+                    true => self.coloring.count(escape.iters, escape.z_2, self.max_iter, power) as f32,
+                    false => -1.0,
+                });
+            }
+        }
+        let histogram = Histogram::new(&counts);
+        counts.iter().flat_map(|n| histogram.color(*n, self.palette.as_ref())).collect()
+    }
+
+    pub fn write_png(&self, filename: &str) -> Result<(), String> {
+        let file = File::create(filename).map_err(|e| e.to_string())?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer.write_image_data(&self.render()).map_err(|e| e.to_string())
+    }
+}
+
+// same escape-time loop as calc_pixel in mandelbrot_frag.glsl, in double precision
+// an orbit that comes back within tolerance of an earlier point is in a cycle and never escapes
+pub fn calc_pixel(
+            coord: &glm::DVec3,
+            fractal: &Fractal,
+            julia: Option<glm::DVec2>,
+            max_iter: i32,
+            coloring: &Coloring,
+            tolerance: f64,
+            trap_image: Option<&TrapImage>
+        ) -> Escape {
+    let point = glm::vec2(coord.x, coord.y);
+    let escape_radius = coloring.escape_radius;
+    let trap = (coloring.mode == ColorMode::Trap).then_some(&coloring.trap);
+    let mut escape = Escape {
+        iters: max_iter,
+        z_2: 0.0,
+        dr: if julia.is_some() { 1.0 } else { 0.0 },
+        period: 0,
+        // what the shaders start from as well
+        trap_distance: 1e30,
+        trap_hit: None,
+    };
+    if *fractal == Fractal::Mandelbrot && julia.is_none() {
+        escape.period = known_period(point);
+        // the trap still needs the orbit
+        if escape.period > 0 && trap.is_none() {
+            return escape;
+        }
+    }
+    let (mut z, c) = match julia {
+        Some(c) => (point, c),
+        None => (glm::vec2(0.0, 0.0), point),
+    };
+    // Brent's cycle detection, the saved point moves ahead after 1, 2, 4, ... iterations
+    let mut saved = z;
+    let (mut steps, mut check) = (0, 1);
+    let mut iter = 0;
+    while glm::dot(&z, &z) <= escape_radius * escape_radius && iter < max_iter {
+        escape.dr = fractal.derivative(glm::length(&z), escape.dr, julia.is_none());
+        z = fractal.step(z, c);
+        iter += 1;
+        if let Some(trap) = trap {
+            escape.trap_distance = escape.trap_distance.min(trap.distance(z));
+            if trap.shape == TrapShape::Image && escape.trap_hit.is_none() {
+                escape.trap_hit = trap_image.and_then(|image| trap.sample(z, image));
+            }
+        }
+        steps += 1;
+        if (z.x - saved.x).abs() + (z.y - saved.y).abs() < tolerance {
+            escape.period = steps;
+            return escape;
+        }
+        if steps == check {
+            saved = z;
+            steps = 0;
+            check *= 2;
+        }
+    }
+    escape.iters = iter;
+    escape.z_2 = glm::dot(&z, &z);
+    escape
+}
+
+// 1 inside the main cardioid of the Mandelbrot set, 2 inside the period 2 bulb, 0 otherwise
+pub fn known_period(c: glm::DVec2) -> i32 {
+    let a = c.x - 0.25;
+    let q = a * a + c.y * c.y;
+    if q * (q + a) <= 0.25 * c.y * c.y {
+        1
+    } else if (c.x + 1.0) * (c.x + 1.0) + c.y * c.y <= 0.0625 {
+        2
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 8x8 pixels of the default view, the square from -1-i to 1+i
+    fn pixel(image: &[u8], col: usize, row: usize) -> [u8; 3] {
+        let i = (row * 8 + col) * 3;
+        [image[i], image[i + 1], image[i + 2]]
+    }
+
+    #[test]
+    fn inside_is_black() {
+        let mut renderer = Renderer::new(8, 8);
+        renderer.max_iter(64).coloring(Coloring::new(ColorMode::Distance));
+        // 0.125-0.125i is in the main cardioid
+        assert_eq!(pixel(&renderer.render(), 4, 4), [0, 0, 0]);
+    }
+
+    #[test]
+    fn escape_count() {
+        let mut renderer = Renderer::new(8, 8);
+        renderer.max_iter(64);
+        // the top left pixel is -0.875+0.875i, it leaves the radius 2 circle after 3 iterations
+        let coloring = Coloring::new(ColorMode::Banded);
+        let escape = calc_pixel(&glm::vec3(-0.875, 0.875, 1.0), &Fractal::Mandelbrot, None, 64, &coloring, 0.0, None);
+        assert_eq!(escape.iters, 3);
+        assert_eq!(pixel(&renderer.render(), 0, 0), coloring::sine_color(3.0));
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let mut renderer = Renderer::new(8, 8);
+        renderer.max_iter(64);
+        let path = std::env::temp_dir().join(format!("render_test_{}.png", std::process::id()));
+        renderer.write_png(path.to_str().unwrap()).unwrap();
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((info.width, info.height, info.color_type), (8, 8, png::ColorType::Rgb));
+        assert_eq!(&data[..info.buffer_size()], &renderer.render()[..]);
+    }
+}
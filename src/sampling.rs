@@ -0,0 +1,192 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::str::FromStr;
+
+use crate::shader::Shader;
+use crate::uniform::Uniform;
+
+// the largest grid the key cycles through, 4 x 4 samples per pixel
+pub const MAX_SIZE: i32 = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SamplePattern {
+    Grid,
+    // the grid turned by atan(1/2), so that no two samples of the 2 x 2 grid share a row or a column
+    RotatedGrid,
+    // one random point in every cell of the grid
+    Jittered,
+}
+
+// anti-aliasing by several samples per pixel, averaged in linear color
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Supersampling {
+    pub pattern: SamplePattern,
+    // size x size samples per pixel, 1 is a single sample in the middle
+    pub size: i32,
+}
+
+impl SamplePattern {
+    // has to match the SAMPLE_* defines of the fragment shaders
+    pub fn id(&self) -> i32 {
+        match self {
+            SamplePattern::Grid => 0,
+            SamplePattern::RotatedGrid => 1,
+            SamplePattern::Jittered => 2,
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            SamplePattern::Grid => SamplePattern::RotatedGrid,
+            SamplePattern::RotatedGrid => SamplePattern::Jittered,
+            SamplePattern::Jittered => SamplePattern::Grid,
+        }
+    }
+}
+
+impl FromStr for SamplePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grid" => Ok(SamplePattern::Grid),
+            "rotated" => Ok(SamplePattern::RotatedGrid),
+            "jittered" => Ok(SamplePattern::Jittered),
+            _ => Err(format!("Unknown sample pattern: {} (grid, rotated, jittered)", s)),
+        }
+    }
+}
+
+impl Supersampling {
+    pub fn new(pattern: SamplePattern, size: i32) -> Self {
+        Self {
+            pattern,
+            size: size.max(1),
+        }
+    }
+
+    // 1, 2, ... MAX_SIZE samples along each side, then 1 again
+    pub fn next_size(&self) -> Self {
+        Self::new(self.pattern, self.size % MAX_SIZE + 1)
+    }
+
+    // positions of the samples inside the pixel (x, y) of the framebuffer, y up, -0.5 to 0.5 from its center
+    // the same as sample_offset in the shaders
+    pub fn offsets(&self, x: u32, y: u32) -> Vec<glm::DVec2> {
+        if self.size == 1 {
+            return vec![glm::vec2(0.0, 0.0)];
+        }
+        let n = self.size as f64;
+        (0..self.size * self.size)
+            .map(|i| {
+                let cell = glm::vec2((i % self.size) as f64, (i / self.size) as f64);
+                match self.pattern {
+                    SamplePattern::Grid => (cell + glm::vec2(0.5, 0.5)) / n - glm::vec2(0.5, 0.5),
+                    SamplePattern::RotatedGrid => {
+                        let g = (cell + glm::vec2(0.5, 0.5)) / n - glm::vec2(0.5, 0.5);
+                        // rotated and scaled back, the 2 x 2 grid becomes the usual (1/8, 3/8) pattern
+                        // the corners of the larger grids stick out of the pixel, they wrap around to the other side
+                        let r = glm::vec2(g.x - 0.5 * g.y, 0.5 * g.x + g.y) + glm::vec2(0.5, 0.5);
+                        glm::vec2(r.x.rem_euclid(1.0), r.y.rem_euclid(1.0)) - glm::vec2(0.5, 0.5)
+                    },
+                    SamplePattern::Jittered => {
+                        let h = hash(x.wrapping_add(hash(y.wrapping_add(hash(i as u32)))));
+                        let random = glm::vec2(h as f64, hash(h) as f64) / 4294967296.0;
+                        (cell + random) / n - glm::vec2(0.5, 0.5)
+                    },
+                }
+            })
+            .collect()
+    }
+
+    pub fn set_uniforms(&self, shader: &mut Shader) {
+        Uniform::new("sample_pattern", shader).seti(self.pattern.id(), shader);
+        Uniform::new("sample_size", shader).seti(self.size, shader);
+    }
+}
+
+// the mean of sRGB colors, taken in linear color so that the edges don't come out too dark
+pub fn average(samples: &[[u8; 3]]) -> [u8; 3] {
+    let mut sum = [0.0; 3];
+    for sample in samples {
+        for channel in 0..3 {
+            sum[channel] += (sample[channel] as f64 / 255.0).powf(2.2);
+        }
+    }
+    sum.map(|channel| ((channel / samples.len() as f64).powf(1.0 / 2.2) * 255.0).round() as u8)
+}
+
+// integer hash for the jitter, the same as hash() in the shaders
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_inside_the_pixel() {
+        for pattern in [SamplePattern::Grid, SamplePattern::RotatedGrid, SamplePattern::Jittered] {
+            for size in 1..=MAX_SIZE {
+                let offsets = Supersampling::new(pattern, size).offsets(17, 5);
+                assert_eq!(offsets.len(), (size * size) as usize);
+                for offset in offsets.iter() {
+                    assert!(offset.x.abs() <= 0.5 && offset.y.abs() <= 0.5, "{:?} {}: {:?}", pattern, size, offset);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rotated_grid() {
+        let offsets = Supersampling::new(SamplePattern::RotatedGrid, 2).offsets(0, 0);
+        let expected = [(-0.125, -0.375), (0.375, -0.125), (-0.375, 0.125), (0.125, 0.375)];
+        for (offset, (x, y)) in offsets.iter().zip(expected) {
+            assert!((offset.x - x).abs() < 1e-12 && (offset.y - y).abs() < 1e-12, "{:?}", offset);
+        }
+        // the samples that wrapped around don't land on the others
+        for size in 2..=MAX_SIZE {
+            let offsets = Supersampling::new(SamplePattern::RotatedGrid, size).offsets(0, 0);
+            for (i, a) in offsets.iter().enumerate() {
+                for b in offsets[i + 1..].iter() {
+                    assert!(glm::distance(a, b) > 1e-9, "{}: {:?} {:?}", size, a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn jitter_depends_on_the_pixel() {
+        let sampling = Supersampling::new(SamplePattern::Jittered, 2);
+        assert_eq!(sampling.offsets(3, 4), sampling.offsets(3, 4));
+        assert_ne!(sampling.offsets(3, 4), sampling.offsets(4, 3));
+    }
+
+    #[test]
+    fn average_in_linear_color() {
+        assert_eq!(average(&[[10, 20, 30]]), [10, 20, 30]);
+        // the mean of black and white is brighter than 128 in sRGB
+        let [gray, ..] = average(&[[0, 0, 0], [255, 255, 255]]);
+        assert!(gray > 180 && gray < 190, "{}", gray);
+    }
+}
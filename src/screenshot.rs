@@ -0,0 +1,82 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use gl::types::GLsizei;
+
+// pictures of the window, written to the working directory as mandelbrot-<date>-<time>-<count>.png
+#[derive(Default)]
+pub struct Screenshots {
+    // taken since the program started, it tells the ones taken in the same second apart
+    count: u32,
+}
+
+impl Screenshots {
+    // what is drawn on the window so far, in the pixels of the drawable, returns the name of the file
+    pub fn save(&mut self, width: u32, height: u32) -> Result<String, String> {
+        let pixels = read_window(width, height);
+        let stamp = timestamp();
+        let filename = loop {
+            self.count += 1;
+            let filename = format!("mandelbrot-{}-{:03}.png", stamp, self.count);
+            if !Path::new(&filename).exists() {
+                break filename;
+            }
+        };
+        let file = File::create(&filename).map_err(|e| format!("{}: {}", filename, e))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer.write_image_data(&pixels).map_err(|e| e.to_string())?;
+        Ok(filename)
+    }
+}
+
+// RGB bytes of the back buffer, rows top to bottom the way PNG wants them
+fn read_window(width: u32, height: u32) -> Vec<u8> {
+    let row = width as usize * 3;
+    let mut pixels = vec![0u8; row * height as usize];
+    unsafe {
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+        gl::ReadBuffer(gl::BACK);
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(0, 0, width as GLsizei, height as GLsizei, gl::RGB, gl::UNSIGNED_BYTE, pixels.as_mut_ptr() as *mut _);
+    }
+    // GL starts from the bottom row
+    pixels.chunks_exact(row).rev().flatten().copied().collect()
+}
+
+// YYYYMMDD-HHMMSS in UTC
+fn timestamp() -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+    let (days, time) = (seconds / 86400, seconds % 86400);
+    // the civil date of a day count, with the years starting in March so that the leap day is the last one
+    let era_days = days + 719468;
+    let era = era_days / 146097;
+    let day_of_era = era_days % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}", year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
@@ -28,7 +28,12 @@ pub struct Shader {
 impl Shader {
 
     pub fn new(vertex: &str, fragment: &str) -> Result<Self,String> {
-        let program: GLuint = Self::create_shader(vertex, fragment)?;
+        Self::with_defines(vertex, fragment, &[])
+    }
+
+    // compiles both stages with a #define line for each name, inserted right after #version
+    pub fn with_defines(vertex: &str, fragment: &str, defines: &[&str]) -> Result<Self,String> {
+        let program: GLuint = Self::create_shader(vertex, fragment, defines)?;
         let mut instance = Self {
             id: program, 
             bound: false
@@ -42,8 +47,25 @@ impl Shader {
             .map_err(|e| e.to_string())
     }
 
-    fn compile_shader(filename: &str, shader_type: GLuint) -> Result<GLuint, String> {
-        let src = Self::load_src(filename);
+    fn insert_defines(src: String, defines: &[&str]) -> String {
+        if defines.is_empty() {
+            return src;
+        }
+        let directives: String = defines.iter()
+            .map(|name| format!("#define {}\n", name))
+            .collect();
+        // the #version directive has to stay the first one in the source
+        match src.find("#version") {
+            Some(start) => {
+                let end = src[start..].find('\n').map(|i| start + i + 1).unwrap_or(src.len());
+                format!("{}{}{}", &src[..end], directives, &src[end..])
+            },
+            None => directives + &src,
+        }
+    }
+
+    fn compile_shader(filename: &str, shader_type: GLuint, defines: &[&str]) -> Result<GLuint, String> {
+        let src = Self::load_src(filename).map(|src| Self::insert_defines(src, defines));
         let shader: GLuint;
         unsafe {
             shader = gl::CreateShader(shader_type);
@@ -62,9 +84,9 @@ impl Shader {
         Ok(shader)
     }
 
-    fn create_shader(vertex: &str, fragment: &str) -> Result<GLuint, String> {
-        let vertex_shader: GLuint = Self::compile_shader(vertex, gl::VERTEX_SHADER)?;
-        let fragment_shader: GLuint = Self::compile_shader(fragment, gl::FRAGMENT_SHADER)?;
+    fn create_shader(vertex: &str, fragment: &str, defines: &[&str]) -> Result<GLuint, String> {
+        let vertex_shader: GLuint = Self::compile_shader(vertex, gl::VERTEX_SHADER, defines)?;
+        let fragment_shader: GLuint = Self::compile_shader(fragment, gl::FRAGMENT_SHADER, defines)?;
 
         let shader_program: GLuint;
         unsafe {
@@ -32,6 +32,9 @@ pub struct Texture {
 pub enum TextureType {
     RGB,
     RGBA,
+    RG32F,
+    R32F,
+    RGBA32F,
 }
 
 #[allow(unused)]
@@ -44,6 +47,8 @@ pub enum InterpolationType {
 pub enum MapType {
     REPEAT,
     MIRRORED,
+    #[allow(clippy::upper_case_acronyms)]
+    CLAMP,
 }
 
 #[allow(unused)]
@@ -65,6 +70,37 @@ impl Texture {
         Ok(instance)
     }   
 
+    // texture from pixel data in memory, laid out the way `ty` says
+    pub fn from_data(buffer: &[u8], width: u32, height: u32, unit_: GLint, ty: TextureType) -> Self {
+        let mut texture: GLuint = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+        }
+        Self::generate(texture, buffer, width, height, unit_, ty);
+        let mut instance = Self {
+            id: texture,
+            unit: unit_,
+            bound: false,
+        };
+        instance.settings(InterpolationType::NEAREST, InterpolationType::NEAREST, MapType::REPEAT, MapType::REPEAT);
+        instance.bind();
+        instance
+    }
+
+    // replaces the whole image, the size may change
+    pub fn set_data(&mut self, buffer: &[u8], width: u32, height: u32, ty: TextureType) {
+        Self::generate(self.id, buffer, width, height, self.unit, ty);
+        self.bound = true;
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn unit(&self) -> GLint {
+        self.unit
+    }
+
     pub fn link(&self, shader_program: &mut Shader, sampler: &str) {
         let tex0 = Uniform::new(sampler, shader_program);
         shader_program.bind();
@@ -72,11 +108,13 @@ impl Texture {
         shader_program.unbind();
     }
 
-    fn read_png(filename: &str) -> Result<(Vec<u8>, u32, u32), String> {
-        let decoder = png::Decoder::new(File::open(filename).map_err(|e| e.to_string())?);
+    pub fn read_png(filename: &str) -> Result<(Vec<u8>, u32, u32), String> {
+        let mut decoder = png::Decoder::new(File::open(filename).map_err(|e| e.to_string())?);
+        // indexed and 16 bit images as plain 8 bit channels
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
         let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
         let mut buf = vec![0; reader.output_buffer_size()];
-        let texture_info = reader.next_frame(&mut buf).unwrap();
+        let texture_info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
         let texture_bytes = &mut buf[..texture_info.buffer_size()]; // these are the bytes of the png
         Ok((texture_bytes.to_owned(), texture_info.width, texture_info.height))
     }
@@ -87,21 +125,26 @@ impl Texture {
             gl::ActiveTexture(((gl::TEXTURE0) as GLint + unit) as GLenum);
             gl::BindTexture(gl::TEXTURE_2D, texture);
         }
-        let format = match ty {
-            TextureType::RGB => gl::RGB,
-            TextureType::RGBA => gl::RGBA,
+        let (internal, format, data) = match ty {
+            TextureType::RGB => (gl::RGBA, gl::RGB, gl::UNSIGNED_BYTE),
+            TextureType::RGBA => (gl::RGBA, gl::RGBA, gl::UNSIGNED_BYTE),
+            TextureType::RG32F => (gl::RG32F, gl::RG, gl::FLOAT),
+            TextureType::R32F => (gl::R32F, gl::RED, gl::FLOAT),
+            TextureType::RGBA32F => (gl::RGBA32F, gl::RGBA, gl::FLOAT),
         };
         // texture generation
         unsafe {
+            // rows of RGB images are not always a multiple of 4 bytes long
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
             gl::TexImage2D(
                 gl::TEXTURE_2D, 
                 0, 
-                gl::RGBA as GLint, 
+                internal as GLint, 
                 width as GLsizei, 
                 height as GLsizei, 
                 0, 
                 format,
-                gl::UNSIGNED_BYTE, 
+                data, 
                 mem::transmute(&buf[0]));
 
             gl::GenerateMipmap(gl::TEXTURE_2D);
@@ -117,8 +160,9 @@ impl Texture {
 
     fn gl_to_enum_map(ty: MapType) -> GLint {
         match ty {
-            MapType::REPEAT => gl::LINEAR as i32,
+            MapType::REPEAT => gl::REPEAT as i32,
             MapType::MIRRORED => gl::MIRRORED_REPEAT as i32,
+            MapType::CLAMP => gl::CLAMP_TO_EDGE as i32,
         }
     }
 
@@ -142,7 +186,7 @@ impl Texture {
     pub fn bind(&mut self) {
         if !self.bound {
             unsafe {
-                gl::ActiveTexture(gl::TEXTURE0);
+                gl::ActiveTexture(((gl::TEXTURE0) as GLint + self.unit) as GLenum);
                 gl::BindTexture(gl::TEXTURE_2D, self.id);
             }
             self.bound = true;
@@ -152,6 +196,7 @@ impl Texture {
     pub fn unbind(&mut self) {
         if self.bound {
             unsafe {
+                gl::ActiveTexture(((gl::TEXTURE0) as GLint + self.unit) as GLenum);
                 gl::BindTexture(gl::TEXTURE_2D, 0);
             }
             self.bound = false;
@@ -0,0 +1,185 @@
+/*
+  Copyright (C) 2023  Tóth Bálint
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use gl::types::GLint;
+use std::str::FromStr;
+
+use crate::shader::Shader;
+use crate::texture::{Texture, TextureType};
+use crate::uniform::Uniform;
+
+pub const TRAP_UNIT: GLint = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrapShape {
+    Point,
+    Line,
+    Cross,
+    Circle,
+    // a picture in a rectangle, the first of its pixels the orbit goes through colors the point
+    Image,
+}
+
+// the shape the orbits are measured against, the colors come from how close they got to it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Trap {
+    pub shape: TrapShape,
+    pub center: glm::DVec2,
+    // radius of the circle, half the width of the image, and the distance of the first color band otherwise
+    pub size: f64,
+    // direction of the line, the cross and the image, in radians
+    pub angle: f64,
+}
+
+// the CPU copy of the image of the image trap
+pub struct TrapImage {
+    buffer: Vec<u8>,
+    width: u32,
+    height: u32,
+    channels: usize,
+}
+
+impl TrapShape {
+    // has to match the TRAP_* defines of the fragment shaders
+    pub fn id(&self) -> i32 {
+        match self {
+            TrapShape::Point => 0,
+            TrapShape::Line => 1,
+            TrapShape::Cross => 2,
+            TrapShape::Circle => 3,
+            TrapShape::Image => 4,
+        }
+    }
+
+    // the image trap is left out when there is no image loaded
+    pub fn next(&self, image: bool) -> Self {
+        match self {
+            TrapShape::Point => TrapShape::Line,
+            TrapShape::Line => TrapShape::Cross,
+            TrapShape::Cross => TrapShape::Circle,
+            TrapShape::Circle if image => TrapShape::Image,
+            TrapShape::Circle | TrapShape::Image => TrapShape::Point,
+        }
+    }
+}
+
+impl FromStr for TrapShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "point" => Ok(TrapShape::Point),
+            "line" => Ok(TrapShape::Line),
+            "cross" => Ok(TrapShape::Cross),
+            "circle" => Ok(TrapShape::Circle),
+            "image" => Ok(TrapShape::Image),
+            _ => Err(format!("Unknown trap: {} (point, line, cross, circle, image)", s)),
+        }
+    }
+}
+
+impl Trap {
+    pub fn new(shape: TrapShape) -> Self {
+        Self {
+            shape,
+            center: glm::vec2(0.0, 0.0),
+            size: 0.5,
+            angle: 0.0,
+        }
+    }
+
+    fn direction(&self) -> glm::DVec2 {
+        glm::vec2(self.angle.cos(), self.angle.sin())
+    }
+
+    // z in the frame of the trap, (along the direction, across it)
+    fn local(&self, z: glm::DVec2) -> glm::DVec2 {
+        let p = z - self.center;
+        let d = self.direction();
+        glm::vec2(glm::dot(&p, &d), d.x * p.y - d.y * p.x)
+    }
+
+    // distance of z from the shape, as trap_orbit in the shaders
+    pub fn distance(&self, z: glm::DVec2) -> f64 {
+        let p = self.local(z);
+        match self.shape {
+            TrapShape::Point => glm::length(&p),
+            TrapShape::Line => p.y.abs(),
+            TrapShape::Cross => p.x.abs().min(p.y.abs()),
+            TrapShape::Circle => (glm::length(&p) - self.size).abs(),
+            TrapShape::Image => f64::INFINITY,
+        }
+    }
+
+    // the color of the image under z, if z is inside the rectangle on an opaque pixel
+    pub fn sample(&self, z: glm::DVec2, image: &TrapImage) -> Option<[u8; 3]> {
+        let p = self.local(z);
+        let aspect = image.height as f64 / image.width as f64;
+        let u = p.x / self.size * 0.5 + 0.5;
+        let v = p.y / (self.size * aspect) * 0.5 + 0.5;
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
+        }
+        // the first row of the image is its top
+        let x = (u * image.width as f64) as usize;
+        let y = ((1.0 - v) * image.height as f64) as usize;
+        image.pixel(x, y.min(image.height as usize - 1))
+    }
+
+    pub fn set_uniforms(&self, shader: &mut Shader) {
+        let direction = self.direction();
+        Uniform::new("trap_shape", shader).seti(self.shape.id(), shader);
+        Uniform::new("trap_center", shader).set_2f(self.center.x as f32, self.center.y as f32, shader);
+        Uniform::new("trap_size", shader).setf(self.size as f32, shader);
+        Uniform::new("trap_direction", shader).set_2f(direction.x as f32, direction.y as f32, shader);
+        Uniform::new("trap_image", shader).seti(TRAP_UNIT, shader);
+    }
+}
+
+impl TrapImage {
+    pub fn load(filename: &str) -> Result<Self, String> {
+        let (buffer, width, height) = Texture::read_png(filename)?;
+        let channels = buffer.len() / (width * height) as usize;
+        if channels < 3 {
+            return Err(format!("{}: trap images have to be RGB or RGBA", filename));
+        }
+        Ok(Self {
+            buffer,
+            width,
+            height,
+            channels,
+        })
+    }
+
+    // the same file for the shaders, on TRAP_UNIT
+    pub fn texture(&self, filename: &str) -> Result<Texture, String> {
+        let ty = match self.channels {
+            3 => TextureType::RGB,
+            _ => TextureType::RGBA,
+        };
+        Texture::new(filename, TRAP_UNIT, ty)
+    }
+
+    // None for the transparent pixels
+    fn pixel(&self, x: usize, y: usize) -> Option<[u8; 3]> {
+        let idx = (y * self.width as usize + x) * self.channels;
+        let pixel = &self.buffer[idx..idx + self.channels];
+        if self.channels == 4 && pixel[3] < 128 {
+            return None;
+        }
+        Some([pixel[0], pixel[1], pixel[2]])
+    }
+}
@@ -57,6 +57,20 @@ impl Uniform {
         }
     }
 
+    pub fn set_2f(&self, x: f32, y: f32, shader: &mut Shader) {
+        shader.bind();
+        unsafe {
+            gl::Uniform2f(self.id, x, y);
+        }
+    }
+
+    pub fn set_2d(&self, x: f64, y: f64, shader: &mut Shader) {
+        shader.bind();
+        unsafe {
+            gl::Uniform2d(self.id, x, y);
+        }
+    }
+
     pub fn set_3f(&self, x: f32, y: f32, z: f32, shader: &mut Shader) {
         shader.bind();
         unsafe {